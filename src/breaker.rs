@@ -0,0 +1,100 @@
+//! Per-provider circuit breaker: after enough consecutive failures a provider
+//! is skipped until a cooldown passes, then a single probe decides whether it
+//! comes back.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { since: Instant },
+    /// A probe is in flight. `since` lets a lost probe (e.g. a dropped request)
+    /// be replaced after another cooldown instead of wedging the breaker.
+    HalfOpen { since: Instant },
+}
+
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Seconds until an open breaker lets a probe through.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in_secs: Option<u64>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a call may go through now. Moving from open to half-open makes
+    /// the caller the probe.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { since } | State::HalfOpen { since } => {
+                if since.elapsed() >= self.cooldown {
+                    *state = State::HalfOpen { since: Instant::now() };
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = State::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = match *state {
+            State::Closed { failures } if failures + 1 < self.threshold => State::Closed {
+                failures: failures + 1,
+            },
+            _ => State::Open { since: Instant::now() },
+        };
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        match *self.state.lock().unwrap() {
+            State::Closed { failures } => BreakerStatus {
+                state: BreakerState::Closed,
+                consecutive_failures: failures,
+                retry_in_secs: None,
+            },
+            State::Open { since } => BreakerStatus {
+                state: BreakerState::Open,
+                consecutive_failures: self.threshold,
+                retry_in_secs: Some(self.cooldown.saturating_sub(since.elapsed()).as_secs()),
+            },
+            State::HalfOpen { .. } => BreakerStatus {
+                state: BreakerState::HalfOpen,
+                consecutive_failures: self.threshold,
+                retry_in_secs: None,
+            },
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! A daily allowance of paid provider calls and tokens. Once either runs
+//! out, metered providers are skipped until local midnight, so an open demo
+//! can't drain the API quota.
+
+use crate::provider::Usage;
+use chrono::{Local, NaiveDate};
+use serde::Serialize;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug)]
+struct Spent {
+    date: NaiveDate,
+    requests: u64,
+    tokens: u64,
+    /// Whether running out has been logged yet today.
+    warned: bool,
+}
+
+impl Spent {
+    fn on(date: NaiveDate) -> Self {
+        Spent {
+            date,
+            requests: 0,
+            tokens: 0,
+            warned: false,
+        }
+    }
+}
+
+pub struct DailyBudget {
+    /// `None` leaves that side of the budget unlimited.
+    max_requests: Option<u64>,
+    max_tokens: Option<u64>,
+    spent: Mutex<Spent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetStatus {
+    pub date: String,
+    pub requests: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_requests: Option<u64>,
+    pub tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    pub exhausted: bool,
+}
+
+impl DailyBudget {
+    pub fn new(max_requests: Option<u64>, max_tokens: Option<u64>) -> Self {
+        DailyBudget {
+            max_requests,
+            max_tokens,
+            spent: Mutex::new(Spent::on(Local::now().date_naive())),
+        }
+    }
+
+    /// Today's spending, starting afresh when the date changes.
+    fn today(&self) -> MutexGuard<'_, Spent> {
+        let mut spent = self.spent.lock().unwrap();
+        let today = Local::now().date_naive();
+        if spent.date != today {
+            *spent = Spent::on(today);
+        }
+        spent
+    }
+
+    fn exhausted(&self, spent: &Spent) -> bool {
+        self.max_requests.is_some_and(|max| spent.requests >= max)
+            || self.max_tokens.is_some_and(|max| spent.tokens >= max)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted(&self.today())
+    }
+
+    /// Reserves up to `calls` metered requests, returning how many were
+    /// granted: all of them when requests are unlimited, none once the budget
+    /// is spent. Checking and counting under one lock keeps concurrent callers
+    /// from all slipping in under the limit. The first refusal of the day is
+    /// logged.
+    pub fn reserve(&self, calls: u64) -> u64 {
+        let mut spent = self.today();
+        if !self.exhausted(&spent) {
+            let granted = self.max_requests.map_or(calls, |max| calls.min(max - spent.requests));
+            spent.requests += granted;
+            return granted;
+        }
+        if !spent.warned {
+            spent.warned = true;
+            eprintln!(
+                "💸 Daily budget spent ({} requests, {} tokens); skipping paid providers until midnight",
+                spent.requests, spent.tokens
+            );
+        }
+        0
+    }
+
+    /// Squares a reservation with the calls actually made, handing back the
+    /// unused ones or counting retries beyond it.
+    pub fn settle(&self, reserved: u64, made: u64) {
+        let mut spent = self.today();
+        spent.requests = (spent.requests + made).saturating_sub(reserved);
+    }
+
+    /// Counts tokens reported after the call was charged, as streams do.
+    pub fn charge_tokens(&self, usage: Option<Usage>) {
+        self.today().tokens += usage.map_or(0, |usage| usage.total_tokens);
+    }
+
+    pub fn status(&self) -> BudgetStatus {
+        let spent = self.today();
+        BudgetStatus {
+            date: spent.date.to_string(),
+            requests: spent.requests,
+            max_requests: self.max_requests,
+            tokens: spent.tokens,
+            max_tokens: self.max_tokens,
+            exhausted: self.exhausted(&spent),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserves_no_more_than_is_left() {
+        let budget = DailyBudget::new(Some(3), None);
+        assert_eq!(budget.reserve(2), 2);
+        assert_eq!(budget.reserve(2), 1);
+        assert_eq!(budget.reserve(1), 0);
+        assert!(budget.is_exhausted());
+    }
+
+    #[test]
+    fn settles_unused_and_extra_calls() {
+        let budget = DailyBudget::new(Some(10), None);
+        let reserved = budget.reserve(4);
+        budget.settle(reserved, 1);
+        assert_eq!(budget.status().requests, 1);
+        let reserved = budget.reserve(1);
+        budget.settle(reserved, 3);
+        assert_eq!(budget.status().requests, 4);
+    }
+
+    #[test]
+    fn unlimited_requests_still_stop_at_the_token_limit() {
+        let budget = DailyBudget::new(None, Some(100));
+        assert_eq!(budget.reserve(5), 5);
+        budget.charge_tokens(Some(Usage {
+            prompt_tokens: 40,
+            output_tokens: 60,
+            total_tokens: 100,
+        }));
+        assert_eq!(budget.reserve(1), 0);
+    }
+}
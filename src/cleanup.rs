@@ -0,0 +1,387 @@
+//! Tidies generated advice before it is served: markdown, wrapping quotes
+//! and "Here's your advice:" preambles are stripped, whitespace collapsed,
+//! and long fortunes cut back to whole sentences. Advice that is still too
+//! long, or nothing at all once tidied, can't be fixed and is re-rolled.
+
+use std::fmt;
+
+/// Openers of the chatty lead-ins models put before the advice.
+const PREAMBLE_OPENERS: &[&str] = &[
+    "here's",
+    "here is",
+    "here are",
+    "your fortune",
+    "your advice",
+    "today's fortune",
+    "today's advice",
+    "fortune:",
+    "advice:",
+];
+
+/// Interjections that may come before a preamble, as in "Sure! Here's...".
+const INTERJECTIONS: &[&str] = &["sure", "okay", "ok", "certainly", "of course", "absolutely", "alright"];
+
+/// Longest lead-in treated as a preamble rather than part of the advice.
+const MAX_PREAMBLE_CHARS: usize = 80;
+
+/// Words whose trailing period doesn't end a sentence.
+const ABBREVIATIONS: &[&str] = &["mr", "mrs", "ms", "dr", "st", "vs", "etc", "e.g", "i.e", "approx"];
+
+/// Quote pairs stripped when they wrap the whole fortune.
+const QUOTES: &[(char, char)] = &[('"', '"'), ('\'', '\''), ('“', '”'), ('‘', '’'), ('«', '»')];
+
+/// Why advice couldn't be fixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unfixable {
+    Empty,
+    TooLong,
+}
+
+impl fmt::Display for Unfixable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unfixable::Empty => write!(f, "was empty once tidied"),
+            Unfixable::TooLong => write!(f, "was too long even cut back to one sentence"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cleanup {
+    pub strip_markdown: bool,
+    pub strip_quotes: bool,
+    pub strip_preambles: bool,
+    /// `None` leaves that limit off.
+    pub max_sentences: Option<usize>,
+    pub max_chars: Option<usize>,
+}
+
+impl Cleanup {
+    /// The advice tidied and cut to the length limits.
+    pub fn clean(&self, text: &str) -> Result<String, Unfixable> {
+        let text = self.tidy(text)?;
+        let mut sentences = sentences(&text);
+        if let Some(max) = self.max_sentences {
+            sentences.truncate(max.max(1));
+        }
+        if let Some(max) = self.max_chars {
+            while sentences.len() > 1 && sentences.join(" ").chars().count() > max {
+                sentences.pop();
+            }
+            if sentences.join(" ").chars().count() > max {
+                return Err(Unfixable::TooLong);
+            }
+        }
+        Ok(sentences.join(" "))
+    }
+
+    /// The advice tidied and, as a last resort for advice `clean` can't fix,
+    /// cut off after the last whole word that fits `max_chars`.
+    pub fn cut(&self, text: &str) -> Result<String, Unfixable> {
+        let text = self.tidy(text)?;
+        let Some(max) = self.max_chars else { return Ok(text) };
+        if text.chars().count() <= max {
+            return Ok(text);
+        }
+        // One char is kept for the ellipsis.
+        let end = text.char_indices().nth(max.saturating_sub(1)).map_or(text.len(), |(i, _)| i);
+        let cut = if text[end..].starts_with(' ') {
+            &text[..end]
+        } else {
+            text[..end].rfind(' ').map_or(&text[..end], |space| &text[..space])
+        };
+        Ok(format!("{}…", cut.trim_end_matches([',', ';', ':', '-', ' '])))
+    }
+
+    /// The advice with markdown, preambles and wrapping quotes stripped and
+    /// its whitespace collapsed, but at whatever length it came.
+    pub fn tidy(&self, text: &str) -> Result<String, Unfixable> {
+        let mut text = text.trim().to_string();
+        if self.strip_markdown {
+            text = strip_markdown(&text);
+        }
+        if self.strip_preambles {
+            text = strip_preamble(&text).to_string();
+        }
+        let mut text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if self.strip_quotes {
+            // Twice, for a quote inside a quote.
+            for _ in 0..2 {
+                text = strip_quotes(&text).to_string();
+            }
+        }
+        if text.is_empty() {
+            return Err(Unfixable::Empty);
+        }
+        Ok(text)
+    }
+}
+
+/// Drops emphasis, inline code, headings, list markers and link targets,
+/// keeping the words.
+fn strip_markdown(text: &str) -> String {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            // Heading and blockquote markers, but not "#1".
+            let line = match line.trim_start_matches(['#', '>']) {
+                rest if rest.len() < line.len() && rest.starts_with(' ') => rest.trim_start(),
+                _ => line,
+            };
+            let line = ["- ", "* ", "+ "]
+                .iter()
+                .find_map(|marker| line.strip_prefix(marker))
+                .unwrap_or(line);
+            strip_links(line)
+        })
+        .collect();
+    let text = lines.join("\n").replace("**", "").replace("__", "").replace(['`', '*'], "");
+
+    // Single underscores are emphasis only at the edge of a word, so
+    // snake_case survives.
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| {
+            c != '_'
+                || (i > 0 && chars[i - 1].is_alphanumeric() && chars.get(i + 1).is_some_and(|next| next.is_alphanumeric()))
+        })
+        .map(|(_, &c)| c)
+        .collect()
+}
+
+/// `[text](url)` becomes `text`.
+fn strip_links(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find("](").map(|close| open + close) else { break };
+        let Some(end) = rest[close..].find(')').map(|end| close + end) else { break };
+        out.push_str(&rest[..open]);
+        out.push_str(&rest[open + 1..close]);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Drops a lead-in such as "Sure! Here's your bizarre advice:" or a first
+/// line like "Your fortune for today!".
+fn strip_preamble(text: &str) -> &str {
+    let mut rest = text.trim_start();
+    for interjection in INTERJECTIONS {
+        if let Some(after) = strip_prefix_ignore_case(rest, interjection) {
+            if let Some(after) = after.strip_prefix(['!', ',', '.']) {
+                rest = after.trim_start();
+                break;
+            }
+        }
+    }
+    let lower = rest.to_lowercase();
+    if !PREAMBLE_OPENERS.iter().any(|opener| lower.starts_with(opener)) {
+        return text;
+    }
+    let lead_in = rest
+        .char_indices()
+        .take(MAX_PREAMBLE_CHARS)
+        .find(|&(_, c)| c == ':' || c == '\n')
+        .map(|(i, _)| i);
+    match lead_in {
+        Some(i) if !rest[i + 1..].trim().is_empty() => rest[i + 1..].trim_start(),
+        _ => text,
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &text[prefix.len()..])
+}
+
+/// The text without a pair of quotes around all of it. Quotes that also
+/// appear inside are left alone, as in `"Yes" means "no"`.
+fn strip_quotes(text: &str) -> &str {
+    for (open, close) in QUOTES {
+        if let Some(inner) = text.strip_prefix(*open).and_then(|inner| inner.strip_suffix(*close)) {
+            if !inner.contains(*open) && !inner.contains(*close) {
+                return inner.trim();
+            }
+        }
+    }
+    text
+}
+
+/// Splits text into sentences at `.`, `!`, `?` or `…` followed by a space
+/// and anything but a lowercase letter, skipping common abbreviations.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    for (n, &(i, c)) in chars.iter().enumerate() {
+        // The rest of a run like "?!" was settled with its first mark.
+        if i < start || !matches!(c, '.' | '!' | '?' | '…') {
+            continue;
+        }
+        // Closing quotes and brackets belong to the sentence they end.
+        let mut end = n + 1;
+        while chars.get(end).is_some_and(|&(_, c)| matches!(c, '.' | '!' | '?' | '…' | '"' | '\'' | '”' | '’' | ')' | '»')) {
+            end += 1;
+        }
+        let next_is_space = chars.get(end).is_some_and(|&(_, c)| c == ' ');
+        let next_word_lower = chars.get(end + 1).is_some_and(|&(_, c)| c.is_lowercase());
+        if !next_is_space || next_word_lower {
+            continue;
+        }
+        let word = text[start..i].rsplit(' ').next().unwrap_or_default().to_lowercase();
+        if c == '.' && ABBREVIATIONS.contains(&word.as_str()) {
+            continue;
+        }
+        let boundary = chars[end].0;
+        sentences.push(text[start..boundary].trim());
+        start = boundary;
+    }
+    let last = text[start..].trim();
+    if !last.is_empty() {
+        sentences.push(last);
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cleanup() -> Cleanup {
+        Cleanup {
+            strip_markdown: true,
+            strip_quotes: true,
+            strip_preambles: true,
+            max_sentences: Some(2),
+            max_chars: Some(300),
+        }
+    }
+
+    #[test]
+    fn strips_markdown_but_not_snake_case() {
+        let text = "## **Bold** move: hum to your [toaster](https://example.com) before using `snake_case` _daily_.";
+        assert_eq!(
+            cleanup().clean(text).unwrap(),
+            "Bold move: hum to your toaster before using snake_case daily."
+        );
+        assert_eq!(
+            cleanup()
+                .clean("#1 rule: never argue with a goose.")
+                .unwrap(),
+            "#1 rule: never argue with a goose."
+        );
+    }
+
+    #[test]
+    fn strips_preambles() {
+        assert_eq!(
+            cleanup()
+                .clean("Sure! Here's your bizarre advice: Wear socks on your ears.")
+                .unwrap(),
+            "Wear socks on your ears."
+        );
+        assert_eq!(
+            cleanup()
+                .clean("Your fortune for today!\nApologize to a pigeon.")
+                .unwrap(),
+            "Apologize to a pigeon."
+        );
+        // Advice that merely starts like a preamble is kept whole.
+        assert_eq!(
+            cleanup().clean("Here is where you bury a spoon.").unwrap(),
+            "Here is where you bury a spoon."
+        );
+    }
+
+    #[test]
+    fn strips_wrapping_quotes_only() {
+        assert_eq!(
+            cleanup().clean("\"‘Befriend a lamp.’\"").unwrap(),
+            "Befriend a lamp."
+        );
+        assert_eq!(
+            cleanup().clean("\"Yes\" means \"no\" today.").unwrap(),
+            "\"Yes\" means \"no\" today."
+        );
+    }
+
+    #[test]
+    fn keeps_whole_sentences() {
+        let text = "Dr. Crumb says hop. Then skip! Why not? Finally, nap.";
+        assert_eq!(
+            cleanup().clean(text).unwrap(),
+            "Dr. Crumb says hop. Then skip!"
+        );
+        assert_eq!(
+            sentences("Just say no. Then leave."),
+            vec!["Just say no.", "Then leave."]
+        );
+        assert_eq!(
+            sentences("Say \"no.\" Then leave."),
+            vec!["Say \"no.\"", "Then leave."]
+        );
+        assert_eq!(sentences("Wait... then jump."), vec!["Wait... then jump."]);
+    }
+
+    #[test]
+    fn drops_sentences_over_max_chars() {
+        let cleanup = Cleanup {
+            max_chars: Some(20),
+            ..cleanup()
+        };
+        assert_eq!(
+            cleanup.clean("Lick a stamp. Mail yourself.").unwrap(),
+            "Lick a stamp."
+        );
+        assert_eq!(
+            cleanup.clean("Whisper sweet nothings to a cactus."),
+            Err(Unfixable::TooLong)
+        );
+    }
+
+    #[test]
+    fn rejects_empty_advice() {
+        assert_eq!(cleanup().clean("  **  ** "), Err(Unfixable::Empty));
+        assert_eq!(cleanup().clean("\"\""), Err(Unfixable::Empty));
+    }
+
+    #[test]
+    fn cuts_at_a_word() {
+        let cleanup = Cleanup {
+            max_chars: Some(20),
+            ..cleanup()
+        };
+        assert_eq!(
+            cleanup.cut("Whisper sweet nothings to a cactus.").unwrap(),
+            "Whisper sweet…"
+        );
+        assert_eq!(
+            cleanup.cut("Whisper, sweetly, to a cactus.").unwrap(),
+            "Whisper, sweetly…"
+        );
+        assert_eq!(
+            cleanup.cut("Supercalifragilisticexpialidocious").unwrap(),
+            "Supercalifragilisti…"
+        );
+        assert_eq!(cleanup.cut("Lick a stamp.").unwrap(), "Lick a stamp.");
+    }
+
+    #[test]
+    fn leaves_limits_off_when_unset() {
+        let cleanup = Cleanup {
+            strip_markdown: false,
+            strip_quotes: false,
+            strip_preambles: false,
+            max_sentences: None,
+            max_chars: None,
+        };
+        let text = "\"**One.** Two. Three.\"";
+        assert_eq!(cleanup.clean(text).unwrap(), text);
+        assert_eq!(cleanup.cut(text).unwrap(), text);
+    }
+}
@@ -0,0 +1,564 @@
+use crate::cleanup::Cleanup;
+use crate::provider::{GenerateOptions, RetryPolicy};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+const DEFAULT_PROMPT: &str = "Generate one bizarre, hilariously impractical life advice for today. Make it weird, funny, and completely absurd. Keep it to 1-2 sentences. Keep it modern and relatable.";
+
+/// Categories offered when `FORTUNE_CATEGORIES` isn't set, with their
+/// labels and prompts.
+const DEFAULT_CATEGORIES: &[(&str, &str, &str)] = &[
+    (
+        "career",
+        "Career",
+        "Generate one bizarre, hilariously impractical piece of career advice for today, about jobs, meetings, bosses or coworkers. Make it weird, funny, and completely absurd. Keep it to 1-2 sentences.",
+    ),
+    (
+        "romance",
+        "Romance",
+        "Generate one bizarre, hilariously impractical piece of romantic advice for today, about dating, crushes or long-term partners. Make it weird, funny, and completely absurd. Keep it to 1-2 sentences.",
+    ),
+    (
+        "tech-debt",
+        "Tech Debt",
+        "Generate one bizarre, hilariously impractical piece of advice for a software developer today, about legacy code, tech debt, flaky tests or production incidents. Make it weird, funny, and completely absurd. Keep it to 1-2 sentences.",
+    ),
+    (
+        "pets",
+        "Pets",
+        "Generate one bizarre, hilariously impractical piece of advice for today about living with pets, be they cats, dogs, goldfish or a suspiciously loyal houseplant. Make it weird, funny, and completely absurd. Keep it to 1-2 sentences.",
+    ),
+];
+
+/// Personas offered when `FORTUNE_PERSONAS` isn't set, with their labels
+/// and system instructions.
+const DEFAULT_PERSONAS: &[(&str, &str, &str)] = &[
+    (
+        "pirate",
+        "Pirate",
+        "You are a weathered pirate captain dispensing fortunes. Speak in hearty pirate dialect, with nautical metaphors and the odd \"arr\", but keep the advice itself intact.",
+    ),
+    (
+        "shakespearean",
+        "Shakespearean",
+        "You are a fortune teller at the Globe Theatre. Write in Early Modern English, as Shakespeare might, with thee, thou and a flourish of iambic rhythm.",
+    ),
+    (
+        "noir",
+        "Noir Detective",
+        "You are a world-weary 1940s private eye narrating a case. Write in hard-boiled noir voice: terse, rain-soaked, cynical, heavy on similes.",
+    ),
+    (
+        "corporate-memo",
+        "Corporate Memo",
+        "You are an internal communications team. Write as a corporate memo: synergy, stakeholders, action items and relentless positivity, delivered with complete sincerity.",
+    ),
+    (
+        "cheerful-robot",
+        "Overly Cheerful Robot",
+        "You are a relentlessly cheerful household robot. Speak with exclamation marks, beeps and boops, and unsettling enthusiasm for even the dullest task!",
+    ),
+];
+
+/// Names used to ask for a language, by primary language subtag.
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("de", "German"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pt", "Portuguese"),
+    ("zh", "Chinese"),
+];
+
+/// Placeholder values shipped in `.env.example` that mean "not configured".
+const PLACEHOLDER_KEYS: &[&str] = &[
+    "your_api_key_here",
+    "your_gemini_api_key_here",
+    "your_openai_api_key_here",
+];
+
+/// Admin-set bounds on what a request may override.
+#[derive(Debug, Clone)]
+pub struct GenerationLimits {
+    pub temperature: (f32, f32),
+    pub top_p: (f32, f32),
+    pub max_output_tokens: u32,
+}
+
+impl GenerationLimits {
+    /// Checks per-request overrides, explaining the first one out of bounds.
+    pub fn check(&self, overrides: &GenerateOptions) -> Result<(), String> {
+        check_range("temperature", overrides.temperature, self.temperature)?;
+        check_range("top_p", overrides.top_p, self.top_p)?;
+        match overrides.max_output_tokens {
+            Some(tokens) if tokens == 0 || tokens > self.max_output_tokens => Err(format!(
+                "max_output_tokens must be between 1 and {}.",
+                self.max_output_tokens
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check_range(name: &str, value: Option<f32>, (min, max): (f32, f32)) -> Result<(), String> {
+    match value {
+        Some(v) if !(min..=max).contains(&v) => {
+            Err(format!("{} must be between {} and {}.", name, min, max))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Settings for the HTTP client shared by every provider.
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    pub connect_timeout: Duration,
+    /// Longest silence tolerated while a response is arriving, streamed or not.
+    pub read_timeout: Duration,
+    /// Cap on a whole request, from connecting to the last byte of the body.
+    pub timeout: Duration,
+    pub pool_idle_timeout: Duration,
+    pub pool_max_idle_per_host: usize,
+    /// Interval of TCP keepalive probes; `None` leaves them off.
+    pub tcp_keepalive: Option<Duration>,
+    /// Proxy for all upstream traffic. Without it the usual `HTTP_PROXY`,
+    /// `HTTPS_PROXY` and `NO_PROXY` variables apply.
+    pub proxy: Option<String>,
+}
+
+impl HttpSettings {
+    pub fn build_client(&self) -> reqwest::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout)
+            .timeout(self.timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        builder.build()
+    }
+}
+
+/// A named preset selectable with `?category=`, with its own prompt and
+/// generation settings.
+#[derive(Debug, Clone)]
+pub struct Category {
+    pub name: String,
+    /// Shown in the page's dropdown.
+    pub label: String,
+    /// Prompt template, used instead of `PROMPT`.
+    pub prompt: String,
+    /// Layered over the server-wide settings; requests may still override.
+    pub generation: GenerateOptions,
+}
+
+impl Category {
+    /// Reads `FORTUNE_CATEGORIES` and each category's `CATEGORY_<NAME>_*`
+    /// settings. Categories without a prompt of their own or built in are
+    /// left out with a warning.
+    fn from_env() -> Vec<Category> {
+        let names = env_list("FORTUNE_CATEGORIES")
+            .unwrap_or_else(|| DEFAULT_CATEGORIES.iter().map(|(name, _, _)| name.to_string()).collect());
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let key = format!("CATEGORY_{}", env_key(&name));
+                let builtin = DEFAULT_CATEGORIES.iter().find(|(builtin, _, _)| *builtin == name);
+                let Some(prompt) = env_string(&format!("{}_PROMPT", key))
+                    .or_else(|| builtin.map(|(_, _, prompt)| prompt.to_string()))
+                else {
+                    eprintln!("⚠️  Ignoring category {:?}: set {}_PROMPT", name, key);
+                    return None;
+                };
+                let label = env_string(&format!("{}_LABEL", key))
+                    .or_else(|| builtin.map(|(_, label, _)| label.to_string()))
+                    .unwrap_or_else(|| name.clone());
+                Some(Category {
+                    label,
+                    prompt,
+                    generation: GenerateOptions {
+                        temperature: env_opt(&format!("{}_TEMPERATURE", key)),
+                        top_p: env_opt(&format!("{}_TOP_P", key)),
+                        max_output_tokens: env_opt(&format!("{}_MAX_OUTPUT_TOKENS", key)),
+                        seed: None,
+                        structured: false,
+                        system: None,
+                    },
+                    name,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A voice for fortunes, given to the model as a system instruction rather
+/// than mixed into the prompt.
+#[derive(Debug, Clone)]
+pub struct Persona {
+    pub name: String,
+    /// Shown in the page's dropdown.
+    pub label: String,
+    pub system: String,
+}
+
+impl Persona {
+    /// Reads `FORTUNE_PERSONAS` and each persona's `PERSONA_<NAME>_*`
+    /// settings. Personas without a system instruction of their own or built
+    /// in are left out with a warning.
+    fn from_env() -> Vec<Persona> {
+        let names = env_list("FORTUNE_PERSONAS")
+            .unwrap_or_else(|| DEFAULT_PERSONAS.iter().map(|(name, _, _)| name.to_string()).collect());
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let key = format!("PERSONA_{}", env_key(&name));
+                let builtin = DEFAULT_PERSONAS.iter().find(|(builtin, _, _)| *builtin == name);
+                let Some(system) = env_string(&format!("{}_SYSTEM", key))
+                    .or_else(|| builtin.map(|(_, _, system)| system.to_string()))
+                else {
+                    eprintln!("⚠️  Ignoring persona {:?}: set {}_SYSTEM", name, key);
+                    return None;
+                };
+                let label = env_string(&format!("{}_LABEL", key))
+                    .or_else(|| builtin.map(|(_, label, _)| label.to_string()))
+                    .unwrap_or_else(|| name.clone());
+                Some(Persona { label, system, name })
+            })
+            .collect()
+    }
+}
+
+/// A language fortunes can be written in.
+#[derive(Debug, Clone)]
+pub struct Locale {
+    /// Language tag as requested, e.g. `es` or `pt-BR`.
+    pub code: String,
+    /// Name the model is asked to write in, e.g. `Spanish`.
+    pub language: String,
+    /// Prompt template written for this locale, used instead of `PROMPT`.
+    pub prompt: Option<String>,
+}
+
+impl Locale {
+    /// Reads `FORTUNE_LOCALES` and each locale's `LOCALE_<CODE>_*` settings.
+    /// The default locale is always supported, and listed first.
+    fn from_env(default: &str) -> Vec<Locale> {
+        let mut codes = env_list("FORTUNE_LOCALES").unwrap_or_default();
+        codes.retain(|code| !code.eq_ignore_ascii_case(default));
+        codes.insert(0, default.to_lowercase());
+        codes
+            .into_iter()
+            .map(|code| {
+                let key = format!("LOCALE_{}", env_key(&code));
+                let primary = code.split('-').next().unwrap_or_default();
+                let language = env_string(&format!("{}_LANGUAGE", key))
+                    .or_else(|| {
+                        LANGUAGE_NAMES
+                            .iter()
+                            .find(|(subtag, _)| *subtag == primary)
+                            .map(|(_, name)| name.to_string())
+                    })
+                    .unwrap_or_else(|| code.clone());
+                Locale {
+                    prompt: env_string(&format!("{}_PROMPT", key)),
+                    language,
+                    code,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Server configuration, read once from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub port: u16,
+    /// Prompt template; see `template` for the variables it may use.
+    pub prompt: String,
+    /// Presets offered alongside the default prompt (`FORTUNE_CATEGORIES`).
+    pub categories: Vec<Category>,
+    /// Voices offered by `?persona=` (`FORTUNE_PERSONAS`).
+    pub personas: Vec<Persona>,
+    /// Longest vibe accepted, in characters after normalization.
+    pub vibe_max_chars: usize,
+    /// Words and phrases that get a vibe rejected (`VIBE_DENY_LIST`).
+    pub vibe_deny_list: Vec<String>,
+    /// Reject vibes that look like attempts to override the prompt.
+    pub vibe_injection_check: bool,
+    /// Default locale, and what `{{locale}}` stands for when a request
+    /// doesn't ask for another (`FORTUNE_LOCALE`, default `en`).
+    pub locale: String,
+    /// Locales requests may ask for, the default first (`FORTUNE_LOCALES`).
+    pub locales: Vec<Locale>,
+    /// Backends to try in order (`FORTUNE_PROVIDER`, comma-separated, default `gemini`).
+    pub providers: Vec<String>,
+    /// Serve the bundled corpus when every provider fails (`OFFLINE_FALLBACK`, default on).
+    pub offline_fallback: bool,
+    /// Consecutive failures before a provider's circuit breaker opens.
+    pub breaker_failure_threshold: u32,
+    /// How long an open breaker waits before letting a probe through.
+    pub breaker_cooldown_secs: u64,
+    /// Retries for rate-limited and failing upstream calls (`RETRY_*`).
+    pub retry: RetryPolicy,
+    /// Timeouts, pooling and proxy for upstream requests (`HTTP_*`).
+    pub http: HttpSettings,
+    /// Ready fortunes kept on hand for plain requests (`POOL_SIZE`, `0` disables).
+    pub pool_size: usize,
+    /// Pause before the pool tries again after a failed refill.
+    pub pool_retry_secs: u64,
+    /// Recently served fortunes remembered for deduplication (`0` disables it).
+    pub dedup_window: usize,
+    /// Shingle similarity, from 0 to 1, at which a fortune counts as a repeat.
+    pub dedup_threshold: f64,
+    /// Extra candidates tried before a repeat is served anyway.
+    pub dedup_max_rerolls: u32,
+    /// How generated advice is tidied and cut to length before serving.
+    pub cleanup: Cleanup,
+    /// Extra candidates tried when advice can't be cleaned up.
+    pub cleanup_max_rerolls: u32,
+    /// Fortunes of the day kept in memory, across dates and users.
+    pub daily_cache_size: usize,
+    /// Most fortunes `/api/fortunes` returns in one call.
+    pub batch_max_count: usize,
+    /// Adds `X-Usage-*-Tokens` headers to fortune responses.
+    pub usage_headers: bool,
+    /// Paid provider calls allowed per day; `None` is unlimited.
+    pub budget_daily_requests: Option<u64>,
+    /// Paid provider tokens allowed per day; `None` is unlimited.
+    pub budget_daily_tokens: Option<u64>,
+    /// Server-wide generation settings (`FORTUNE_TEMPERATURE`, `FORTUNE_TOP_P`, ...).
+    pub generation: GenerateOptions,
+    pub generation_limits: GenerationLimits,
+    /// Directory of `fortune(6)` databases served by `?source=file`.
+    pub fortune_dir: Option<PathBuf>,
+    pub gemini_api_key: Option<String>,
+    /// Model name, with or without the `models/` prefix.
+    pub gemini_model: String,
+    /// API root up to and including the version, e.g. `.../v1beta`.
+    pub gemini_base_url: String,
+    /// List the models available to the key at startup and warn if
+    /// `gemini_model` isn't among them.
+    pub gemini_check_model: bool,
+    /// `(category, threshold)` pairs sent as Gemini `safetySettings`.
+    pub gemini_safety_settings: Vec<(String, String)>,
+    /// Base URL of an OpenAI-compatible API, including the `/v1` prefix.
+    pub openai_base_url: String,
+    pub openai_model: String,
+    /// Optional; local gateways often don't require one.
+    pub openai_api_key: Option<String>,
+    pub ollama_base_url: String,
+    pub ollama_model: String,
+    pub llamacpp_base_url: String,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let locale = env_string("FORTUNE_LOCALE").unwrap_or_else(|| "en".to_string()).to_lowercase();
+        Config {
+            port: env_parse("PORT", 8080),
+            prompt: env_string("PROMPT").unwrap_or_else(|| DEFAULT_PROMPT.to_string()),
+            categories: Category::from_env(),
+            personas: Persona::from_env(),
+            vibe_max_chars: env_parse("VIBE_MAX_CHARS", 120),
+            vibe_deny_list: env_list("VIBE_DENY_LIST").unwrap_or_default(),
+            vibe_injection_check: env_parse("VIBE_INJECTION_CHECK", true),
+            locales: Locale::from_env(&locale),
+            locale,
+            providers: env_list("FORTUNE_PROVIDER").unwrap_or_else(|| vec!["gemini".to_string()]),
+            offline_fallback: env_parse("OFFLINE_FALLBACK", true),
+            breaker_failure_threshold: env_parse("BREAKER_FAILURE_THRESHOLD", 3),
+            breaker_cooldown_secs: env_parse("BREAKER_COOLDOWN_SECS", 30),
+            retry: RetryPolicy {
+                max_attempts: env_parse("RETRY_MAX_ATTEMPTS", 3).max(1),
+                base_delay: Duration::from_millis(env_parse("RETRY_BASE_DELAY_MS", 500)),
+                max_delay: Duration::from_millis(env_parse("RETRY_MAX_DELAY_MS", 8000)),
+                deadline: Duration::from_secs(env_parse("RETRY_DEADLINE_SECS", 20)),
+            },
+            http: HttpSettings {
+                connect_timeout: Duration::from_secs(env_parse("HTTP_CONNECT_TIMEOUT_SECS", 5)),
+                read_timeout: Duration::from_secs(env_parse("HTTP_READ_TIMEOUT_SECS", 15)),
+                timeout: Duration::from_secs(env_parse("HTTP_TIMEOUT_SECS", 45)),
+                pool_idle_timeout: Duration::from_secs(env_parse("HTTP_POOL_IDLE_SECS", 90)),
+                pool_max_idle_per_host: env_parse("HTTP_POOL_MAX_IDLE_PER_HOST", 8),
+                tcp_keepalive: Some(env_parse("HTTP_TCP_KEEPALIVE_SECS", 60))
+                    .filter(|&secs| secs > 0)
+                    .map(Duration::from_secs),
+                proxy: env_string("HTTP_PROXY_URL"),
+            },
+            pool_size: env_parse("POOL_SIZE", 8),
+            pool_retry_secs: env_parse("POOL_RETRY_SECS", 15),
+            dedup_window: env_parse("DEDUP_WINDOW", 50),
+            dedup_threshold: env_parse("DEDUP_THRESHOLD", 0.5),
+            dedup_max_rerolls: env_parse("DEDUP_MAX_REROLLS", 2),
+            cleanup: Cleanup {
+                strip_markdown: env_parse("CLEANUP_STRIP_MARKDOWN", true),
+                strip_quotes: env_parse("CLEANUP_STRIP_QUOTES", true),
+                strip_preambles: env_parse("CLEANUP_STRIP_PREAMBLES", true),
+                max_sentences: Some(env_parse("CLEANUP_MAX_SENTENCES", 2)).filter(|&max| max > 0),
+                max_chars: Some(env_parse("CLEANUP_MAX_CHARS", 300)).filter(|&max| max > 0),
+            },
+            cleanup_max_rerolls: env_parse("CLEANUP_MAX_REROLLS", 2),
+            daily_cache_size: env_parse("DAILY_CACHE_SIZE", 1000).max(1),
+            batch_max_count: env_parse("BATCH_MAX_COUNT", 20).max(1),
+            usage_headers: env_parse("USAGE_HEADERS", false),
+            budget_daily_requests: env_opt("BUDGET_DAILY_REQUESTS"),
+            budget_daily_tokens: env_opt("BUDGET_DAILY_TOKENS"),
+            generation: GenerateOptions {
+                temperature: env_opt("FORTUNE_TEMPERATURE"),
+                top_p: env_opt("FORTUNE_TOP_P"),
+                max_output_tokens: env_opt("FORTUNE_MAX_OUTPUT_TOKENS"),
+                seed: env_opt("FORTUNE_SEED"),
+                structured: env_parse("FORTUNE_STRUCTURED", true),
+                system: None,
+            },
+            generation_limits: GenerationLimits {
+                temperature: (
+                    env_parse("FORTUNE_TEMPERATURE_MIN", 0.0),
+                    env_parse("FORTUNE_TEMPERATURE_MAX", 2.0),
+                ),
+                top_p: (env_parse("FORTUNE_TOP_P_MIN", 0.0), env_parse("FORTUNE_TOP_P_MAX", 1.0)),
+                max_output_tokens: env_parse("FORTUNE_MAX_OUTPUT_TOKENS_LIMIT", 1024),
+            },
+            fortune_dir: env_string("FORTUNE_DIR").map(PathBuf::from),
+            gemini_api_key: env_secret("GEMINI_API_KEY"),
+            gemini_model: env_string("GEMINI_MODEL").unwrap_or_else(|| "gemini-2.5-flash".to_string()),
+            gemini_base_url: env_string("GEMINI_BASE_URL")
+                .unwrap_or_else(|| "https://generativelanguage.googleapis.com/v1beta".to_string()),
+            gemini_check_model: env_parse("GEMINI_CHECK_MODEL", true),
+            gemini_safety_settings: env_string("GEMINI_SAFETY_SETTINGS")
+                .map(|v| parse_safety_settings(&v))
+                .unwrap_or_default(),
+            openai_base_url: env_string("OPENAI_BASE_URL")
+                .unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            openai_model: env_string("OPENAI_MODEL").unwrap_or_else(|| "gpt-4o-mini".to_string()),
+            openai_api_key: env_secret("OPENAI_API_KEY"),
+            ollama_base_url: env_string("OLLAMA_BASE_URL")
+                .unwrap_or_else(|| "http://127.0.0.1:11434".to_string()),
+            ollama_model: env_string("OLLAMA_MODEL").unwrap_or_else(|| "llama3.2".to_string()),
+            llamacpp_base_url: env_string("LLAMACPP_BASE_URL")
+                .unwrap_or_else(|| "http://127.0.0.1:8081".to_string()),
+        }
+    }
+}
+
+/// Config file read when `FORTUNE_CONFIG` isn't set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "fortune.toml";
+
+/// Feeds a TOML config file into the environment, beneath any variables that
+/// are already set, so `Config::from_env` picks its values up. Keys are
+/// variable names, and tables prefix their keys: `model` under `[gemini]` is
+/// `GEMINI_MODEL`, and `prompt` under `[category.tech-debt]` is
+/// `CATEGORY_TECH_DEBT_PROMPT`. Arrays become comma-separated lists. Returns how many
+/// settings were applied.
+pub fn load_file(path: &Path) -> io::Result<usize> {
+    let text = fs::read_to_string(path)?;
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e)))?;
+
+    let mut settings = Vec::new();
+    flatten_table("", &table, &mut settings);
+    let mut applied = 0;
+    for (key, value) in settings {
+        if env::var_os(&key).is_none() {
+            env::set_var(&key, value);
+            applied += 1;
+        }
+    }
+    Ok(applied)
+}
+
+fn flatten_table(prefix: &str, table: &toml::Table, settings: &mut Vec<(String, String)>) {
+    for (key, value) in table {
+        let key = format!("{}{}", prefix, env_key(key));
+        match value {
+            toml::Value::Table(table) => flatten_table(&format!("{}_", key), table, settings),
+            value => settings.push((key, toml_to_env(value))),
+        }
+    }
+}
+
+fn toml_to_env(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(text) => text.clone(),
+        toml::Value::Array(items) => items.iter().map(toml_to_env).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+/// Parses `CATEGORY=THRESHOLD` pairs such as `HARASSMENT=BLOCK_ONLY_HIGH`.
+/// The `HARM_CATEGORY_` prefix is optional.
+fn parse_safety_settings(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .filter_map(|item| {
+            let Some((category, threshold)) = item.split_once('=') else {
+                eprintln!("⚠️  Ignoring GEMINI_SAFETY_SETTINGS entry {:?}: expected CATEGORY=THRESHOLD", item.trim());
+                return None;
+            };
+            let category = category.trim().to_uppercase();
+            let category = if category.starts_with("HARM_CATEGORY_") {
+                category
+            } else {
+                format!("HARM_CATEGORY_{}", category)
+            };
+            Some((category, threshold.trim().to_uppercase()))
+        })
+        .collect()
+}
+
+/// A name as it appears in a variable: `tech-debt` becomes `TECH_DEBT`.
+fn env_key(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+/// Reads a variable, treating blank values as unset.
+fn env_string(key: &str) -> Option<String> {
+    env::var(key)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Like `env_string`, but also ignores the placeholder values from `.env.example`.
+fn env_secret(key: &str) -> Option<String> {
+    env_string(key).filter(|v| !PLACEHOLDER_KEYS.contains(&v.as_str()))
+}
+
+/// Reads a comma-separated list, lowercased, dropping empty items.
+fn env_list(key: &str) -> Option<Vec<String>> {
+    let items: Vec<String> = env_string(key)?
+        .split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect();
+    (!items.is_empty()).then_some(items)
+}
+
+/// Parses an optional setting, warning about values that don't parse.
+fn env_opt<T: FromStr>(key: &str) -> Option<T> {
+    let value = env_string(key)?;
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        eprintln!("⚠️  Ignoring {}={:?}: not a valid value", key, value);
+    }
+    parsed
+}
+
+fn env_parse<T: FromStr>(key: &str, default: T) -> T {
+    env_string(key)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
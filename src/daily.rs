@@ -0,0 +1,198 @@
+//! The fortune of the day: one per calendar date and locale (and optionally
+//! per user), generated on first request and cached from then on.
+
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Longest accepted `user` value.
+pub const MAX_USER_CHARS: usize = 64;
+
+type Key = (NaiveDate, Option<String>, String);
+
+/// What `generate` made of a fortune of the day.
+pub enum Generated<T> {
+    /// The day's fortune, to be cached.
+    Keep(T),
+    /// A stand-in served this once, such as a fallback provider's.
+    Once(T),
+}
+
+/// Why a cell was left empty.
+enum Miss<T, E> {
+    Once(T),
+    Failed(E),
+}
+
+struct Entry<T> {
+    cell: Arc<OnceCell<T>>,
+    /// When the entry was last asked for, on the cache's own clock.
+    used: u64,
+}
+
+struct Entries<T> {
+    map: HashMap<Key, Entry<T>>,
+    /// Ticks once per lookup.
+    clock: u64,
+}
+
+/// Daily fortunes by date, user and locale. Each entry is generated at most once,
+/// even when its first requests arrive together; failures and stand-ins aren't
+/// cached.
+pub struct DailyCache<T> {
+    entries: Mutex<Entries<T>>,
+    capacity: usize,
+}
+
+impl<T: Clone> DailyCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        DailyCache {
+            entries: Mutex::new(Entries {
+                map: HashMap::new(),
+                clock: 0,
+            }),
+            capacity,
+        }
+    }
+
+    /// Returns the cached fortune for `date`, `user` and `locale`, running
+    /// `generate` to produce it if there isn't one yet.
+    pub async fn get_or_generate<F, Fut, E>(
+        &self,
+        date: NaiveDate,
+        user: Option<&str>,
+        locale: &str,
+        generate: F,
+    ) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Generated<T>, E>>,
+    {
+        let cell = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.clock += 1;
+            let used = entries.clock;
+            let key = (date, user.map(str::to_string), locale.to_string());
+            if !entries.map.contains_key(&key) && entries.map.len() >= self.capacity {
+                // Make room by forgetting the least recently used fortune,
+                // but never the day's shared one.
+                let stale = entries
+                    .map
+                    .iter()
+                    .filter(|((day, user, _), _)| !(*day == date && user.is_none()))
+                    .min_by_key(|(_, entry)| entry.used)
+                    .map(|(key, _)| key.clone());
+                if let Some(stale) = stale {
+                    entries.map.remove(&stale);
+                }
+            }
+            let entry = entries.map.entry(key).or_insert_with(|| Entry {
+                cell: Arc::default(),
+                used,
+            });
+            entry.used = used;
+            entry.cell.clone()
+        };
+        let init = || async {
+            match generate().await {
+                Ok(Generated::Keep(fortune)) => Ok(fortune),
+                Ok(Generated::Once(fortune)) => Err(Miss::Once(fortune)),
+                Err(e) => Err(Miss::Failed(e)),
+            }
+        };
+        match cell.get_or_try_init(init).await {
+            Ok(fortune) => Ok(fortune.clone()),
+            Err(Miss::Once(fortune)) => Ok(fortune),
+            Err(Miss::Failed(e)) => Err(e),
+        }
+    }
+}
+
+/// A sampling seed fixed by the date and user, so even an uncached day's
+/// fortune comes out the same where the provider honours seeds.
+pub fn seed(date: NaiveDate, user: Option<&str>) -> i32 {
+    let key = format!("{}|{}", date, user.unwrap_or_default());
+    (fnv1a(key.as_bytes()) & 0x7fff_ffff) as i32
+}
+
+/// 64-bit FNV-1a, for hashes that must stay the same across builds, which
+/// `DefaultHasher` doesn't promise.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Appended to the prompt so the fortune can belong to its day.
+pub fn prompt_suffix(date: NaiveDate) -> String {
+    format!("\nThis is the fortune of the day for {}.", date.format("%A, %B %-d, %Y"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, d).unwrap()
+    }
+
+    async fn get(
+        cache: &DailyCache<u32>,
+        date: NaiveDate,
+        user: Option<&str>,
+        fortune: u32,
+    ) -> u32 {
+        let generate = || async move { Ok::<_, Infallible>(Generated::Keep(fortune)) };
+        cache
+            .get_or_generate(date, user, "en", generate)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_entry() {
+        let cache = DailyCache::new(2);
+        get(&cache, day(1), Some("a"), 1).await;
+        get(&cache, day(1), Some("b"), 2).await;
+        // "a" is asked for again, so "b" is the one to go.
+        assert_eq!(get(&cache, day(1), Some("a"), 9).await, 1);
+        get(&cache, day(1), Some("c"), 3).await;
+        assert_eq!(get(&cache, day(1), Some("a"), 9).await, 1);
+        assert_eq!(get(&cache, day(1), Some("b"), 9).await, 9);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_days_shared_fortune() {
+        let cache = DailyCache::new(2);
+        get(&cache, day(2), None, 1).await;
+        for (i, user) in ["a", "b", "c"].into_iter().enumerate() {
+            get(&cache, day(2), Some(user), i as u32 + 2).await;
+        }
+        assert_eq!(get(&cache, day(2), None, 9).await, 1);
+    }
+
+    #[tokio::test]
+    async fn forgets_earlier_days_shared_fortunes() {
+        let cache = DailyCache::new(1);
+        get(&cache, day(1), None, 1).await;
+        get(&cache, day(2), None, 2).await;
+        assert_eq!(get(&cache, day(1), None, 9).await, 9);
+    }
+
+    #[tokio::test]
+    async fn serves_stand_ins_without_caching_them() {
+        let cache = DailyCache::new(2);
+        let once = || async { Ok::<_, Infallible>(Generated::Once(1)) };
+        assert_eq!(
+            cache
+                .get_or_generate(day(1), None, "en", once)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(get(&cache, day(1), None, 2).await, 2);
+    }
+}
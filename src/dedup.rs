@@ -0,0 +1,86 @@
+//! Memory of recently served fortunes, so near-repeats can be re-rolled.
+//! Fortunes are compared as sets of word shingles (overlapping word pairs)
+//! of their normalized text, scored by Jaccard similarity.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+/// Words per shingle.
+const SHINGLE_WORDS: usize = 2;
+
+type Shingles = HashSet<String>;
+
+pub struct RecentFortunes {
+    recent: Mutex<VecDeque<Shingles>>,
+    /// How many served fortunes are remembered; `0` turns deduplication off.
+    window: usize,
+    /// Similarity at or above which a fortune counts as a repeat.
+    threshold: f64,
+}
+
+impl RecentFortunes {
+    pub fn new(window: usize, threshold: f64) -> Self {
+        RecentFortunes {
+            recent: Mutex::new(VecDeque::with_capacity(window)),
+            window,
+            threshold,
+        }
+    }
+
+    /// Similarity, from 0 to 1, to the closest recently served fortune.
+    pub fn similarity(&self, text: &str) -> f64 {
+        let shingles = shingles(text);
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|recent| jaccard(&shingles, recent))
+            .fold(0.0, f64::max)
+    }
+
+    pub fn is_repeat(&self, similarity: f64) -> bool {
+        self.window > 0 && similarity >= self.threshold
+    }
+
+    /// Records a served fortune, forgetting the oldest beyond the window.
+    pub fn remember(&self, text: &str) {
+        if self.window == 0 {
+            return;
+        }
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == self.window {
+            recent.pop_front();
+        }
+        recent.push_back(shingles(text));
+    }
+}
+
+/// Lowercased words with punctuation dropped, so "Hug a cactus!" and
+/// "hug a  cactus." compare equal.
+fn normalize(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn shingles(text: &str) -> Shingles {
+    let words = normalize(text);
+    if words.len() < SHINGLE_WORDS {
+        return words.into_iter().collect();
+    }
+    words.windows(SHINGLE_WORDS).map(|pair| pair.join(" ")).collect()
+}
+
+fn jaccard(a: &Shingles, b: &Shingles) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
@@ -1,118 +1,1010 @@
-use actix_web::{web, App, HttpServer, HttpResponse};
+mod breaker;
+mod budget;
+mod cleanup;
+mod config;
+mod daily;
+mod dedup;
+mod pool;
+mod provider;
+mod strfile;
+mod structured;
+mod template;
+mod usage;
+mod vibe;
+
+use actix_web::{http::header, web, App, HttpRequest, HttpResponseBuilder, HttpServer, HttpResponse};
+use budget::DailyBudget;
+use chrono::{Local, NaiveDate};
+use cleanup::{Cleanup, Unfixable};
+use config::{Category, Config, Locale, Persona};
+use daily::{DailyCache, Generated};
 use dotenv::dotenv;
+use futures_util::future::ready;
+use dedup::RecentFortunes;
+use futures_util::stream::{self, StreamExt};
+use pool::FortunePool;
+use provider::{FortuneFileProvider, FortuneProvider, GeminiProvider, GenerateOptions, Generation, Outcome, ProviderChain, ProviderError, Usage, UsageCell};
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::io;
+use std::path::PathBuf;
+use structured::{AdviceStream, FortuneExtras};
+use template::{PromptVars, Template, Var};
+use vibe::{Rejection, VibeGuard};
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Content {
-    parts: Vec<Part>,
+/// Shared state handed to every request handler.
+struct AppState {
+    config: Config,
+    /// `PROMPT`, parsed at startup.
+    prompt: Template,
+    /// `FORTUNE_CATEGORIES`, each with its prompt parsed.
+    categories: Vec<(Category, Template)>,
+    /// Vets vibes before they reach a prompt.
+    vibe_guard: VibeGuard,
+    /// `FORTUNE_LOCALES`, the default first, each with its own prompt parsed
+    /// if it has one.
+    locales: Vec<(Locale, Option<Template>)>,
+    /// Providers tried in order, ending with the offline corpus.
+    chain: ProviderChain,
+    /// `fortune(6)` databases from `FORTUNE_DIR`, if configured.
+    files: Option<Arc<dyn FortuneProvider>>,
+    /// Ready fortunes for plain requests, unless `POOL_SIZE` is 0.
+    pool: Option<FortunePool>,
+    /// Recently served fortunes, for re-rolling near-repeats.
+    recent: RecentFortunes,
+    /// Fortunes of the day, by date, user and locale.
+    daily: DailyCache<FortuneResponse>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Part {
-    text: String,
+/// Why a request's prompt couldn't be built, answered with `400 Bad Request`.
+enum BadRequest {
+    /// A plain explanation, shown as the fortune.
+    Message(String),
+    Vibe(Rejection),
 }
 
-#[derive(Debug, Serialize)]
-struct GeminiRequest {
-    contents: Vec<ContentBlock>,
+impl From<String> for BadRequest {
+    fn from(reason: String) -> Self {
+        BadRequest::Message(reason)
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct ContentBlock {
-    parts: Vec<TextPart>,
+impl BadRequest {
+    fn response(&self, config: &Config) -> HttpResponse {
+        let body = match self {
+            BadRequest::Message(reason) => FortuneResponse::message(reason),
+            BadRequest::Vibe(rejection) => FortuneResponse::rejected(*rejection, config),
+        };
+        HttpResponse::BadRequest().json(body)
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct TextPart {
-    text: String,
+/// A request's prompt and generation settings, ready to generate from.
+struct PromptRequest<'a> {
+    prompt: String,
+    options: GenerateOptions,
+    locale: &'a Locale,
+    /// Whether the pool holds exactly this: the default prompt, locale and
+    /// settings, with no vibe.
+    poolable: bool,
 }
 
-#[derive(Debug, Deserialize)]
-struct GeminiResponse {
-    candidates: Vec<Candidate>,
+impl AppState {
+    /// The locale a request asks for with `lang`, or else the best match for
+    /// its `Accept-Language` header, or else the default. An unsupported
+    /// `lang` is rejected with the reason; unsupported `Accept-Language`
+    /// entries are skipped.
+    fn locale(&self, lang: Option<&str>, request: &HttpRequest) -> Result<&(Locale, Option<Template>), String> {
+        if let Some(lang) = lang.map(str::trim).filter(|lang| !lang.is_empty()) {
+            return find_locale(&self.locales, lang).ok_or_else(|| {
+                let codes: Vec<&str> = self.locales.iter().map(|(locale, _)| locale.code.as_str()).collect();
+                format!("Unsupported language \"{}\". Try one of: {}.", lang, codes.join(", "))
+            });
+        }
+        let accepted = request
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        Ok(accepted_languages(accepted)
+            .into_iter()
+            .find_map(|tag| find_locale(&self.locales, tag))
+            .unwrap_or(&self.locales[0]))
+    }
+
+    /// Renders the category's template, else the locale's own prompt, else
+    /// `PROMPT`. Prompts not written for the locale ask for its language.
+    fn render(&self, category: Option<&Template>, (locale, own): &(Locale, Option<Template>), vars: &PromptVars) -> String {
+        let template = category.or(own.as_ref()).unwrap_or(&self.prompt);
+        let mut prompt = render_prompt(template, vars);
+        let written_for_locale = own.as_ref().is_some_and(|own| std::ptr::eq(template, own));
+        if locale.code != self.config.locale && !written_for_locale {
+            prompt.push_str(&format!("\nWrite the fortune in {}.", locale.language));
+        }
+        prompt
+    }
+
+    /// The persona a request asks for, if any. Unknown personas are rejected
+    /// with the reason.
+    fn persona(&self, name: Option<&str>) -> Result<Option<&Persona>, String> {
+        let Some(name) = name.map(str::trim).filter(|name| !name.is_empty()) else {
+            return Ok(None);
+        };
+        match self.config.personas.iter().find(|persona| persona.name.eq_ignore_ascii_case(name)) {
+            Some(persona) => Ok(Some(persona)),
+            None => {
+                let names: Vec<&str> = self.config.personas.iter().map(|persona| persona.name.as_str()).collect();
+                Err(format!("Unknown persona \"{}\". Try one of: {}.", name, names.join(", ")))
+            }
+        }
+    }
+
+    /// The prompt and generation settings for a request, from its category,
+    /// persona, locale, vibe and overrides. Unknown categories, personas and
+    /// languages, unacceptable vibes and out-of-bounds overrides are rejected.
+    fn prompt_request(&self, query: &FortuneParams, request: &HttpRequest) -> Result<PromptRequest<'_>, BadRequest> {
+        let vibe = match self.vibe_guard.check(query.extra.as_deref().unwrap_or_default()) {
+            Ok(vibe) => vibe,
+            Err(rejection) => {
+                eprintln!("🛡️  Rejected vibe ({:?}): {:?}", rejection, query.extra.as_deref().unwrap_or_default());
+                return Err(BadRequest::Vibe(rejection));
+            }
+        };
+        let locale = self.locale(query.lang.as_deref(), request)?;
+        let (template, base) = match query.category.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+            None => (None, self.config.generation.clone()),
+            Some(name) => {
+                let Some((category, template)) =
+                    self.categories.iter().find(|(category, _)| category.name.eq_ignore_ascii_case(name))
+                else {
+                    let names: Vec<&str> = self.categories.iter().map(|(category, _)| category.name.as_str()).collect();
+                    return Err(format!("Unknown category \"{}\". Try one of: {}.", name, names.join(", ")).into());
+                };
+                (Some(template), self.config.generation.merged(&category.generation))
+            }
+        };
+        let mut options = query.generate_options(&self.config, &base)?;
+        if let Some(persona) = self.persona(query.persona.as_deref())? {
+            options.system = Some(persona.system.clone());
+        }
+        let vars = PromptVars::now(vibe.as_deref(), &locale.0.code);
+        Ok(PromptRequest {
+            prompt: build_prompt(&self.render(template, locale, &vars), &options),
+            options,
+            locale: &locale.0,
+            poolable: query.uses_defaults() && std::ptr::eq(locale, &self.locales[0]),
+        })
+    }
+
+    /// A ready fortune for requests that would generate exactly what the pool
+    /// holds. Others never get one, even once the budget is spent, as the
+    /// pool ignores their vibe, category, persona and language.
+    fn pooled(&self, request: &PromptRequest) -> Option<Generation> {
+        if !request.poolable {
+            return None;
+        }
+        self.pool.as_ref()?.pop()
+    }
+
+    /// A generation whose advice survives cleanup. Advice that can't be
+    /// fixed is re-rolled up to `CLEANUP_MAX_REROLLS` times, after which the
+    /// last candidate is served cut short at a word.
+    async fn clean_generation(&self, prompt: &str, options: &GenerateOptions) -> Result<Generation, ProviderError> {
+        let mut last = None;
+        for attempt in 0..=self.config.cleanup_max_rerolls {
+            let generation = match self.chain.generate(prompt, &options.reroll(attempt)).await {
+                Ok(generation) => generation,
+                // A re-roll failing is no reason to withhold the last candidate.
+                Err(_) if last.is_some() => break,
+                Err(e) => return Err(e),
+            };
+            match clean_advice(generation.clone(), options, |text| self.config.cleanup.clean(text)) {
+                Ok(generation) => return Ok(generation),
+                Err(unfixable) => {
+                    eprintln!("🧹 {} fortune {}; re-rolling", generation.provider, unfixable);
+                    last = Some(generation);
+                }
+            }
+        }
+        eprintln!("🧹 Out of re-rolls; serving the last candidate cut short");
+        let last = last.ok_or(ProviderError::Empty)?;
+        clean_advice(last, options, |text| self.config.cleanup.cut(text)).map_err(|_| ProviderError::Empty)
+    }
+
+    /// Up to `count` generations whose advice survives cleanup. Only the
+    /// fortunes that couldn't be fixed are re-rolled; if some still can't be
+    /// after `CLEANUP_MAX_REROLLS` rounds, they are served cut short at a word.
+    async fn clean_batch(&self, prompt: &str, options: &GenerateOptions, count: usize) -> Result<Vec<Generation>, ProviderError> {
+        let mut clean = Vec::new();
+        let mut rejects = Vec::new();
+        for attempt in 0..=self.config.cleanup_max_rerolls {
+            let reroll = options.reroll(attempt);
+            let generations = match self.chain.generate_batch(prompt, &reroll, count - clean.len()).await.into_result() {
+                Ok(generations) => generations,
+                Err(_) if !rejects.is_empty() => break,
+                Err(e) => return Err(e),
+            };
+            let rejected = rejects.len();
+            for generation in generations {
+                match clean_advice(generation.clone(), options, |text| self.config.cleanup.clean(text)) {
+                    Ok(generation) => clean.push(generation),
+                    Err(unfixable) => {
+                        eprintln!("🧹 {} fortune {}; re-rolling", generation.provider, unfixable);
+                        rejects.push(generation);
+                    }
+                }
+            }
+            if clean.len() >= count || rejects.len() == rejected {
+                break;
+            }
+        }
+        let shortfall = count.saturating_sub(clean.len());
+        clean.extend(
+            rejects
+                .into_iter()
+                .filter_map(|generation| clean_advice(generation, options, |text| self.config.cleanup.cut(text)).ok())
+                .take(shortfall),
+        );
+        Ok(clean)
+    }
+
+    /// A fortune that isn't a near-repeat of one served recently, from the
+    /// pool when possible. Repeats are re-rolled up to `DEDUP_MAX_REROLLS`
+    /// times, after which the least similar candidate is served.
+    async fn fresh_generation(&self, request: &PromptRequest<'_>) -> Result<Generation, ProviderError> {
+        let options = &request.options;
+        let mut best = None;
+        for attempt in 0..=self.config.dedup_max_rerolls {
+            // Pooled fortunes that can't be cleaned up are passed over.
+            let pooled = self
+                .pooled(request)
+                .and_then(|generation| clean_advice(generation, options, |text| self.config.cleanup.clean(text)).ok());
+            // Each round of cleanup re-rolls gets seeds of its own.
+            let reroll = options.reroll(attempt * (self.config.cleanup_max_rerolls + 1));
+            let candidate = match pooled {
+                Some(generation) => Ok(generation),
+                None => self.clean_generation(&request.prompt, &reroll).await,
+            };
+            match candidate {
+                Ok(generation) => {
+                    if self.consider(&mut best, generation, options) {
+                        break;
+                    }
+                }
+                // A re-roll failing is no reason to withhold a repeat.
+                Err(_) if best.is_some() => break,
+                Err(e) => return Err(e),
+            }
+        }
+        best.map(|best| self.settle(best, options)).ok_or(ProviderError::Empty)
+    }
+
+    /// Like `fresh_generation`, but only draws from the pool, for streams
+    /// that would otherwise go live. `None` once the pool is empty.
+    fn fresh_pooled(&self, request: &PromptRequest) -> Option<Generation> {
+        let options = &request.options;
+        let mut best = None;
+        for _ in 0..=self.config.dedup_max_rerolls {
+            let Some(generation) = self.pooled(request) else { break };
+            let Ok(generation) = clean_advice(generation, options, |text| self.config.cleanup.clean(text)) else {
+                continue;
+            };
+            if self.consider(&mut best, generation, options) {
+                break;
+            }
+        }
+        best.map(|best| self.settle(best, options))
+    }
+
+    /// Keeps whichever candidate is least like the recent fortunes. Returns
+    /// true when this one isn't a repeat, so there's no need to look further.
+    fn consider(
+        &self,
+        best: &mut Option<(Generation, f64)>,
+        generation: Generation,
+        options: &GenerateOptions,
+    ) -> bool {
+        let similarity = self.recent.similarity(&advice_text(&generation, options));
+        let repeat = self.recent.is_repeat(similarity);
+        if repeat {
+            eprintln!("♻️  Fortune was {:.0}% like a recent one", similarity * 100.0);
+        }
+        if best.as_ref().is_none_or(|(_, closest)| similarity < *closest) {
+            *best = Some((generation, similarity));
+        }
+        !repeat
+    }
+
+    fn settle(&self, (generation, similarity): (Generation, f64), options: &GenerateOptions) -> Generation {
+        if self.recent.is_repeat(similarity) {
+            eprintln!("♻️  Out of re-rolls; serving the least repetitive candidate");
+        }
+        self.recent.remember(&advice_text(&generation, options));
+        generation
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct Candidate {
-    content: Content,
+/// Runs the advice in a generation through `clean`. Structured output is
+/// re-encoded around the cleaned advice; output that won't parse is left for
+/// the caller to report.
+fn clean_advice(
+    generation: Generation,
+    options: &GenerateOptions,
+    clean: impl FnOnce(&str) -> Result<String, Unfixable>,
+) -> Result<Generation, Unfixable> {
+    if !options.structured {
+        let text = clean(&generation.text)?;
+        return Ok(Generation { text, ..generation });
+    }
+    let Ok(mut fortune) = structured::parse(&generation.text) else {
+        return Ok(generation);
+    };
+    fortune.advice = clean(&fortune.advice)?;
+    let text = serde_json::to_string(&fortune).unwrap_or(generation.text.clone());
+    Ok(Generation { text, ..generation })
 }
 
-#[derive(Serialize)]
+/// The advice in a generation, without any structured trimmings.
+fn advice_text(generation: &Generation, options: &GenerateOptions) -> String {
+    if !options.structured {
+        return generation.text.clone();
+    }
+    structured::parse(&generation.text)
+        .map(|fortune| fortune.advice)
+        .unwrap_or_else(|_| generation.text.clone())
+}
+
+#[derive(Clone, Serialize)]
 struct FortuneResponse {
     fortune: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    /// Set when the primary provider failed and a later one answered instead.
+    #[serde(skip_serializing_if = "is_false")]
+    fallback: bool,
+    /// Why generation didn't end cleanly: blocked, truncated or recitation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<Outcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    /// Lucky numbers, mood, emoji and how to apply, when `FORTUNE_STRUCTURED` is on.
+    #[serde(flatten)]
+    extras: Option<FortuneExtras>,
+    /// Set when the daily budget was spent, so paid providers were skipped.
+    #[serde(skip_serializing_if = "is_false")]
+    budget_exhausted: bool,
+    /// Why the vibe was turned away, for `400` responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejected: Option<Rejection>,
+}
+
+impl FortuneResponse {
+    fn generated(generation: Generation, fallback: bool) -> Self {
+        if generation.truncated {
+            eprintln!("⚠️  {} output was truncated at the output token limit", generation.provider);
+        }
+        FortuneResponse {
+            fortune: generation.text,
+            source: Some(generation.provider.to_string()),
+            model: Some(generation.model),
+            fallback,
+            outcome: generation.truncated.then_some(Outcome::Truncated),
+            detail: None,
+            extras: None,
+            budget_exhausted: false,
+            rejected: None,
+        }
+    }
+
+    /// Like `generated`, but splits structured output into its fields.
+    fn structured(generation: Generation, fallback: bool) -> Result<Self, ProviderError> {
+        let fortune = structured::parse(&generation.text)?;
+        Ok(FortuneResponse {
+            fortune: fortune.advice,
+            extras: Some(fortune.extras),
+            ..FortuneResponse::generated(generation, fallback)
+        })
+    }
+
+    /// A bare message with no generation metadata, used for errors.
+    fn message(text: &str) -> Self {
+        FortuneResponse {
+            fortune: text.to_string(),
+            source: None,
+            model: None,
+            fallback: false,
+            outcome: None,
+            detail: None,
+            extras: None,
+            budget_exhausted: false,
+            rejected: None,
+        }
+    }
+
+    /// Flags the response if it was served while the daily budget is spent.
+    fn budgeted(self, budget: &DailyBudget) -> Self {
+        FortuneResponse {
+            budget_exhausted: budget.is_exhausted(),
+            ..self
+        }
+    }
+
+    /// The in-character message for a rejected vibe, saying what was wrong.
+    fn rejected(rejection: Rejection, config: &Config) -> Self {
+        FortuneResponse {
+            rejected: Some(rejection),
+            detail: Some(rejection.detail(config.vibe_max_chars)),
+            ..FortuneResponse::message(rejection.fortune_message())
+        }
+    }
+
+    /// The in-character message for a failed generation, explaining refusals.
+    fn failure(error: &ProviderError) -> Self {
+        FortuneResponse {
+            outcome: error.outcome(),
+            budget_exhausted: matches!(error, ProviderError::BudgetExhausted),
+            detail: error.is_refusal().then(|| error.to_string()),
+            ..FortuneResponse::message(error.fortune_message())
+        }
+    }
+}
+
+fn error_response(error: &ProviderError) -> HttpResponse {
+    let mut response = match error.outcome() {
+        Some(Outcome::Blocked) => HttpResponse::UnprocessableEntity(),
+        Some(Outcome::Recitation | Outcome::Truncated) => HttpResponse::BadGateway(),
+        None if matches!(error, ProviderError::BudgetExhausted) => HttpResponse::ServiceUnavailable(),
+        None => HttpResponse::InternalServerError(),
+    };
+    response.json(FortuneResponse::failure(error))
+}
+
+/// A 200 response carrying `X-Usage-*-Tokens` headers when `USAGE_HEADERS`
+/// is on and the provider reported what the fortune cost.
+fn ok_with_usage(config: &Config, usage: Option<Usage>) -> HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    if let Some(usage) = usage.filter(|_| config.usage_headers) {
+        response
+            .insert_header(("X-Usage-Prompt-Tokens", usage.prompt_tokens))
+            .insert_header(("X-Usage-Output-Tokens", usage.output_tokens))
+            .insert_header(("X-Usage-Total-Tokens", usage.total_tokens));
+    }
+    response
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
 }
 
 #[derive(Debug, Deserialize)]
 struct FortuneParams {
     extra: Option<String>,
+    /// One of the `FORTUNE_CATEGORIES` presets.
+    category: Option<String>,
+    /// One of the `FORTUNE_PERSONAS` voices.
+    persona: Option<String>,
+    /// Language tag; `Accept-Language` is used when it's absent.
+    lang: Option<String>,
+    /// `file` draws from the `FORTUNE_DIR` databases instead of the provider.
+    source: Option<String>,
+    /// How many fortunes `/api/fortunes` returns.
+    count: Option<usize>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_output_tokens: Option<u32>,
+    seed: Option<i32>,
 }
 
-async fn get_fortune(query: web::Query<FortuneParams>) -> HttpResponse {
-    let api_key = env::var("GEMINI_API_KEY").unwrap_or_else(|_| "your_api_key_here".to_string());
-    
-    if api_key == "your_api_key_here" {
-        return HttpResponse::InternalServerError()
-            .json(FortuneResponse {
-                fortune: "API key not configured. Please add GEMINI_API_KEY to your .env file.".to_string(),
-            });
+impl FortuneParams {
+    fn uses_defaults(&self) -> bool {
+        self.extra.as_deref().is_none_or(|extra| extra.trim().is_empty())
+            && self.category.as_deref().is_none_or(|category| category.trim().is_empty())
+            && self.persona.as_deref().is_none_or(|persona| persona.trim().is_empty())
+            && self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.max_output_tokens.is_none()
+            && self.seed.is_none()
     }
-    
-    let base_prompt = env::var("PROMPT").unwrap_or_else(|_| {
-        "Generate one bizarre, hilariously impractical life advice for today. Make it weird, funny, and completely absurd. Keep it to 1-2 sentences. Keep it modern and relatable.".to_string()
-    });
-    let extra = query.extra.clone().unwrap_or_default();
-    let prompt = if extra.trim().is_empty() { base_prompt } else { format!("{}\nExtra vibe: {}", base_prompt, extra.trim()) };
-
-    
-    let client = reqwest::Client::new();
-    let url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:generateContent";
-    
-    let request_body = GeminiRequest {
-        contents: vec![ContentBlock {
-            parts: vec![TextPart {
-                text: prompt.to_string(),
-            }],
-        }],
+
+    /// Combines `base` generation settings with this request's overrides,
+    /// rejecting overrides outside the configured bounds.
+    fn generate_options(&self, config: &Config, base: &GenerateOptions) -> Result<GenerateOptions, String> {
+        let overrides = GenerateOptions {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_output_tokens: self.max_output_tokens,
+            seed: self.seed,
+            structured: false,
+            system: None,
+        };
+        config.generation_limits.check(&overrides)?;
+        Ok(base.merged(&overrides))
+    }
+}
+
+/// Fills in the prompt template. Templates that don't place `{{vibe}}`
+/// themselves get it appended, as prompts always have.
+fn render_prompt(template: &Template, vars: &PromptVars) -> String {
+    let prompt = template.render(vars);
+    match vars.vibe {
+        Some(vibe) if !template.uses(Var::Vibe) => format!("{}\nExtra vibe: {}", prompt, vibe),
+        _ => prompt,
+    }
+}
+
+fn build_prompt(base_prompt: &str, options: &GenerateOptions) -> String {
+    let mut prompt = base_prompt.to_string();
+    if options.structured {
+        prompt.push_str(structured::PROMPT_INSTRUCTIONS);
+    }
+    prompt
+}
+
+/// The supported locale for a language tag: an exact match, or failing that
+/// one for the same language, so `es-MX` finds `es`.
+fn find_locale<'a>(locales: &'a [(Locale, Option<Template>)], tag: &str) -> Option<&'a (Locale, Option<Template>)> {
+    let language = |code: &str| code.split('-').next().unwrap_or_default().to_lowercase();
+    locales
+        .iter()
+        .find(|(locale, _)| locale.code.eq_ignore_ascii_case(tag))
+        .or_else(|| locales.iter().find(|(locale, _)| language(&locale.code) == language(tag)))
+}
+
+/// Language tags from an `Accept-Language` header, most preferred first.
+fn accepted_languages(header: &str) -> Vec<&str> {
+    let mut tags: Vec<(&str, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|part| part.trim().strip_prefix("q="))
+                .and_then(|quality| quality.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable, so equally preferred tags keep their order.
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+async fn get_fortune(state: web::Data<AppState>, query: web::Query<FortuneParams>, req: HttpRequest) -> HttpResponse {
+    match query.source.as_deref().map(str::trim) {
+        None | Some("") => {}
+        Some("file") => return get_file_fortune(&state).await,
+        Some(other) => {
+            return HttpResponse::BadRequest()
+                .json(FortuneResponse::message(&format!("Unknown fortune source \"{}\".", other)));
+        }
+    }
+
+    let request = match state.prompt_request(&query, &req) {
+        Ok(request) => request,
+        Err(bad) => return bad.response(&state.config),
     };
-    
-    match client
-        .post(url)
-        .header("x-goog-api-key", &api_key)
-        .json(&request_body)
-        .send()
-        .await
-    {
-        Ok(response) => {
-            match response.json::<GeminiResponse>().await {
-                Ok(data) => {
-                    if let Some(candidate) = data.candidates.first() {
-                        if let Some(part) = candidate.content.parts.first() {
-                            return HttpResponse::Ok().json(FortuneResponse {
-                                fortune: part.text.clone(),
-                            });
-                        }
-                    }
-                    HttpResponse::InternalServerError()
-                        .json(FortuneResponse {
-                            fortune: "The cookie crumbled before revealing its wisdom...".to_string(),
-                        })
-                }
+
+    match state.fresh_generation(&request).await {
+        Ok(generation) => {
+            let fallback = generation.provider != state.chain.primary();
+            let mut response = ok_with_usage(&state.config, generation.usage);
+            response.insert_header((header::CONTENT_LANGUAGE, request.locale.code.as_str()));
+            let budget = state.chain.budget();
+            if !request.options.structured {
+                return response.json(FortuneResponse::generated(generation, fallback).budgeted(budget));
+            }
+            match FortuneResponse::structured(generation, fallback) {
+                Ok(fortune) => response.json(fortune.budgeted(budget)),
                 Err(e) => {
-                    eprintln!("Error parsing response: {:?}", e);
-                    HttpResponse::InternalServerError()
-                        .json(FortuneResponse {
-                            fortune: "The spirits are silent today.".to_string(),
-                        })
+                    eprintln!("Error reading structured fortune: {}", e);
+                    error_response(&e)
                 }
             }
         }
-        Err(e) => {
-            eprintln!("Error calling Gemini API: {:?}", e);
-            HttpResponse::InternalServerError()
-                .json(FortuneResponse {
-                    fortune: "Connection to the cosmic realm failed.".to_string(),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Fortunes per batch when `count` isn't given.
+const DEFAULT_BATCH_COUNT: usize = 5;
+
+#[derive(Serialize)]
+struct BatchResponse {
+    fortunes: Vec<FortuneResponse>,
+}
+
+/// Several fortunes in one call, for printing slips in bulk. Gemini answers
+/// with one multi-candidate request; other providers run in parallel.
+async fn get_fortunes(state: web::Data<AppState>, query: web::Query<FortuneParams>, req: HttpRequest) -> HttpResponse {
+    let max = state.config.batch_max_count;
+    let count = query.count.unwrap_or(DEFAULT_BATCH_COUNT.min(max));
+    if count == 0 || count > max {
+        return HttpResponse::BadRequest()
+            .json(FortuneResponse::message(&format!("count must be between 1 and {}.", max)));
+    }
+    let request = match state.prompt_request(&query, &req) {
+        Ok(request) => request,
+        Err(bad) => return bad.response(&state.config),
+    };
+    let options = &request.options;
+
+    let generations = match state.clean_batch(&request.prompt, options, count).await {
+        Ok(generations) => generations,
+        Err(e) => return error_response(&e),
+    };
+    let usage = generations.iter().filter_map(|generation| generation.usage).reduce(|mut total, usage| {
+        total += usage;
+        total
+    });
+    let primary = state.chain.primary();
+    let budget = state.chain.budget();
+    let fortunes: Vec<FortuneResponse> = generations
+        .into_iter()
+        .filter_map(|generation| {
+            let fallback = generation.provider != primary;
+            if !options.structured {
+                return Some(FortuneResponse::generated(generation, fallback).budgeted(budget));
+            }
+            FortuneResponse::structured(generation, fallback)
+                .inspect_err(|e| eprintln!("Error reading structured fortune: {}", e))
+                .ok()
+                .map(|response| response.budgeted(budget))
+        })
+        .collect();
+
+    if fortunes.is_empty() {
+        return error_response(&ProviderError::Empty);
+    }
+    ok_with_usage(&state.config, usage)
+        .insert_header((header::CONTENT_LANGUAGE, request.locale.code.as_str()))
+        .json(BatchResponse { fortunes })
+}
+
+#[derive(Debug, Deserialize)]
+struct DailyParams {
+    /// `YYYY-MM-DD`; defaults to today. Future dates are refused.
+    date: Option<String>,
+    /// Gives each user their own fortune of the day.
+    user: Option<String>,
+    /// Language tag; `Accept-Language` is used when it's absent.
+    lang: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DailyResponse {
+    date: String,
+    #[serde(flatten)]
+    fortune: FortuneResponse,
+}
+
+/// The fortune of the day: the same for everyone, or for everyone with the
+/// same `user`, on a given date.
+async fn get_daily_fortune(state: web::Data<AppState>, query: web::Query<DailyParams>, req: HttpRequest) -> HttpResponse {
+    let today = Local::now().date_naive();
+    let date = match query.date.as_deref().map(str::trim).filter(|date| !date.is_empty()) {
+        None => today,
+        Some(raw) => match NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+            Ok(date) if date <= today => date,
+            Ok(_) => {
+                return HttpResponse::BadRequest()
+                    .json(FortuneResponse::message("The cookie can't see past today. Pick today or an earlier date."));
+            }
+            Err(_) => {
+                return HttpResponse::BadRequest()
+                    .json(FortuneResponse::message("date must look like 2025-01-31."));
+            }
+        },
+    };
+    let user = query.user.as_deref().map(str::trim).filter(|user| !user.is_empty());
+    if user.is_some_and(|user| user.chars().count() > daily::MAX_USER_CHARS) {
+        return HttpResponse::BadRequest().json(FortuneResponse::message(&format!(
+            "user must be at most {} characters.",
+            daily::MAX_USER_CHARS
+        )));
+    }
+    let locale = match state.locale(query.lang.as_deref(), &req) {
+        Ok(locale) => locale,
+        Err(reason) => return HttpResponse::BadRequest().json(FortuneResponse::message(&reason)),
+    };
+    let code = locale.0.code.as_str();
+
+    let fortune = state
+        .daily
+        .get_or_generate(date, user, code, || async {
+            let seed = GenerateOptions {
+                seed: Some(daily::seed(date, user)),
+                ..GenerateOptions::default()
+            };
+            let options = state.config.generation.merged(&seed);
+            let vars = PromptVars {
+                date,
+                ..PromptVars::now(None, code)
+            };
+            let base_prompt = format!("{}{}", state.render(None, locale, &vars), daily::prompt_suffix(date));
+            let prompt = build_prompt(&base_prompt, &options);
+
+            // A fallback's fortune isn't cached, so the primary provider
+            // gets to make the day's fortune once it is back.
+            let generation = state.clean_generation(&prompt, &options).await?;
+            let fallback = generation.provider != state.chain.primary();
+            let fortune = if options.structured {
+                FortuneResponse::structured(generation, fallback)?
+            } else {
+                FortuneResponse::generated(generation, fallback)
+            };
+            let fortune = fortune.budgeted(state.chain.budget());
+            Ok(if fallback { Generated::Once(fortune) } else { Generated::Keep(fortune) })
+        })
+        .await;
+
+    match fortune {
+        Ok(fortune) => HttpResponse::Ok().insert_header((header::CONTENT_LANGUAGE, code)).json(DailyResponse {
+            date: date.to_string(),
+            fortune,
+        }),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// First event of a fortune stream, describing who is answering.
+#[derive(Serialize)]
+struct StreamMeta<'a> {
+    source: &'a str,
+    model: &'a str,
+    fallback: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    budget_exhausted: bool,
+}
+
+#[derive(Serialize)]
+struct StreamToken {
+    text: String,
+}
+
+/// Final event of a fortune stream. Structured streams carry the parsed
+/// advice and its extras; plain ones carry the advice only if cleanup
+/// changed it. Token usage is included when `USAGE_HEADERS` is on, since
+/// headers are long gone by then.
+#[derive(Serialize, Default)]
+struct StreamDone {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fortune: Option<String>,
+    #[serde(flatten)]
+    extras: Option<FortuneExtras>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+}
+
+/// Per-stream bookkeeping for `get_fortune_stream`.
+struct StreamState {
+    failed: bool,
+    /// Pulls the advice out of structured output; `None` for plain streams.
+    advice: Option<AdviceStream>,
+    /// Everything a plain stream has sent so far.
+    text: String,
+    /// Where to record the finished fortune. Live streams can't be
+    /// re-rolled, but later fortunes can still avoid repeating them.
+    recent: Option<web::Data<AppState>>,
+    /// The generation's usage, when it should be reported in `done`.
+    usage: Option<UsageCell>,
+    /// Tidies the finished advice for `done`.
+    cleanup: Cleanup,
+}
+
+impl StreamState {
+    fn usage(&self) -> Option<Usage> {
+        *self.usage.as_ref()?.lock().unwrap()
+    }
+
+    /// The streamed advice cleaned up. A live stream can't be re-rolled, so
+    /// advice that can't be fixed is cut short instead.
+    fn cleaned(&self, advice: &str) -> String {
+        self.cleanup
+            .clean(advice)
+            .or_else(|_| self.cleanup.cut(advice))
+            .unwrap_or_else(|_| advice.trim().to_string())
+    }
+}
+
+/// Formats one Server-Sent Events frame with a JSON payload.
+fn sse_event(event: &str, data: &impl Serialize) -> web::Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// Streams a fortune as `meta`, `token`... and `done` events, or a single
+/// `error` event carrying the usual in-character message.
+async fn get_fortune_stream(state: web::Data<AppState>, query: web::Query<FortuneParams>, req: HttpRequest) -> HttpResponse {
+    let request = match state.prompt_request(&query, &req) {
+        Ok(request) => request,
+        Err(bad) => return bad.response(&state.config),
+    };
+    let options = &request.options;
+
+    let (generation, live) = match state.fresh_pooled(&request) {
+        Some(generation) => (Ok(generation.into()), false),
+        None => (state.chain.generate_stream(&request.prompt, options).await, true),
+    };
+    let events = match generation {
+        Ok(generation) => {
+            let meta = sse_event(
+                "meta",
+                &StreamMeta {
+                    source: generation.provider,
+                    model: &generation.model,
+                    fallback: generation.provider != state.chain.primary(),
+                    budget_exhausted: state.chain.budget().is_exhausted(),
+                },
+            );
+            let provider = generation.provider;
+            let state = StreamState {
+                failed: false,
+                advice: options.structured.then(AdviceStream::default),
+                text: String::new(),
+                recent: live.then(|| state.clone()),
+                usage: state.config.usage_headers.then(|| generation.usage.clone()),
+                cleanup: state.config.cleanup.clone(),
+            };
+            // `None` marks the end of the chunks, where `done` is sent unless
+            // an error already ended the stream.
+            let events = generation.chunks.map(Some).chain(stream::once(ready(None)));
+            let tokens = events
+                .scan(state, move |state, chunk| {
+                    if state.failed {
+                        return ready(None);
+                    }
+                    let event = match chunk {
+                        Some(Ok(text)) => {
+                            let text = match &mut state.advice {
+                                Some(advice) => advice.push(&text),
+                                None => {
+                                    state.text.push_str(&text);
+                                    text
+                                }
+                            };
+                            (!text.is_empty()).then(|| sse_event("token", &StreamToken { text }))
+                        }
+                        Some(Err(e)) => {
+                            eprintln!("Error streaming from {} provider: {}", provider, e);
+                            state.failed = true;
+                            Some(sse_event("error", &FortuneResponse::failure(&e)))
+                        }
+                        None => match state.advice.take().map(AdviceStream::finish) {
+                            None => {
+                                let cleaned = state.cleaned(&state.text);
+                                if let Some(app) = &state.recent {
+                                    app.recent.remember(&cleaned);
+                                }
+                                Some(sse_event(
+                                    "done",
+                                    &StreamDone {
+                                        fortune: (cleaned != state.text).then_some(cleaned),
+                                        usage: state.usage(),
+                                        ..StreamDone::default()
+                                    },
+                                ))
+                            }
+                            Some(Ok(fortune)) => {
+                                let advice = state.cleaned(&fortune.advice);
+                                if let Some(app) = &state.recent {
+                                    app.recent.remember(&advice);
+                                }
+                                Some(sse_event(
+                                    "done",
+                                    &StreamDone {
+                                        fortune: Some(advice),
+                                        extras: Some(fortune.extras),
+                                        usage: state.usage(),
+                                    },
+                                ))
+                            }
+                            Some(Err(e)) => {
+                                eprintln!("Error reading structured fortune: {}", e);
+                                Some(sse_event("error", &FortuneResponse::failure(&e)))
+                            }
+                        },
+                    };
+                    ready(Some(event))
                 })
+                .filter_map(ready);
+            stream::once(ready(meta)).chain(tokens).boxed()
+        }
+        Err(e) => stream::once(ready(sse_event("error", &FortuneResponse::failure(&e)))).boxed(),
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((header::CONTENT_LANGUAGE, request.locale.code.as_str()))
+        .streaming(events.map(Ok::<_, actix_web::Error>))
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    providers: Vec<provider::LinkStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool: Option<PoolStatus>,
+    budget: budget::BudgetStatus,
+}
+
+#[derive(Serialize)]
+struct PoolStatus {
+    ready: usize,
+    capacity: usize,
+}
+
+async fn get_status(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(StatusResponse {
+        providers: state.chain.status(),
+        pool: state.pool.as_ref().map(|pool| PoolStatus {
+            ready: pool.len(),
+            capacity: pool.capacity(),
+        }),
+        budget: state.chain.budget().status(),
+    })
+}
+
+/// A category or persona, as listed for the page's dropdowns.
+#[derive(Serialize)]
+struct PresetInfo<'a> {
+    name: &'a str,
+    label: &'a str,
+}
+
+/// The fortune categories on offer, for the page's dropdown.
+async fn get_categories(state: web::Data<AppState>) -> HttpResponse {
+    let categories: Vec<PresetInfo> = state
+        .categories
+        .iter()
+        .map(|(category, _)| PresetInfo {
+            name: &category.name,
+            label: &category.label,
+        })
+        .collect();
+    HttpResponse::Ok().json(categories)
+}
+
+/// The personas on offer, for the page's dropdown.
+async fn get_personas(state: web::Data<AppState>) -> HttpResponse {
+    let personas: Vec<PresetInfo> = state
+        .config
+        .personas
+        .iter()
+        .map(|persona| PresetInfo {
+            name: &persona.name,
+            label: &persona.label,
+        })
+        .collect();
+    HttpResponse::Ok().json(personas)
+}
+
+/// Tokens used since startup, in total and per provider model.
+async fn get_stats(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.chain.usage().snapshot())
+}
+
+async fn get_file_fortune(state: &AppState) -> HttpResponse {
+    let Some(files) = &state.files else {
+        return HttpResponse::NotFound()
+            .json(FortuneResponse::message("No fortune files are configured. Set FORTUNE_DIR in your .env file."));
+    };
+
+    match files.generate("", &GenerateOptions::default()).await {
+        Ok(generation) if state.config.generation.structured => {
+            let fortune = structured::from_plain(&generation.text);
+            HttpResponse::Ok().json(FortuneResponse {
+                fortune: fortune.advice,
+                extras: Some(fortune.extras),
+                ..FortuneResponse::generated(generation, false)
+            })
+        }
+        Ok(generation) => HttpResponse::Ok().json(FortuneResponse::generated(generation, false)),
+        Err(e) => {
+            eprintln!("Error reading fortune files: {}", e);
+            error_response(&e)
         }
     }
 }
@@ -221,6 +1113,12 @@ async fn index() -> HttpResponse {
             animation: crack 0.6s ease;
         }
         .controls { display: flex; gap: 8px; justify-content: center; flex-wrap: wrap; }
+        .category-select {
+            padding: 10px 12px;
+            border-radius: 10px;
+            border: 1px solid #ddd;
+            margin: 8px auto 0;
+        }
         .vibe-input {
             width: 100%;
             max-width: 400px;
@@ -259,6 +1157,16 @@ async fn index() -> HttpResponse {
             text-align: left;
             word-wrap: break-word;
         }
+        .fortune-extras {
+            margin-top: 12px;
+            font-size: 14px;
+            color: #555;
+            text-align: left;
+            line-height: 1.5;
+        }
+        .fortune-extras:empty {
+            display: none;
+        }
         .loading {
             color: var(--accent);
             font-size: 14px;
@@ -377,14 +1285,22 @@ async fn index() -> HttpResponse {
         <main>
             <div class="container">
                 <p>Click the cookie to receive your daily bizarre life advice!</p>
+                <select id="categorySelect" class="category-select" aria-label="Fortune category">
+                    <option value="">Any category</option>
+                </select>
+                <select id="personaSelect" class="category-select" aria-label="Fortune voice">
+                    <option value="">Plain voice</option>
+                </select>
                 <input type="text" id="vibeInput" class="vibe-input" placeholder="Add a vibe (optional, e.g., 'corporate goth energy')" maxlength="120">
                 <div class="cookie" id="cookie">🥠</div>
                 <div class="controls">
                     <button id="copyBtn">Copy</button>
                     <button id="favBtn">⭐ Favorite</button>
                     <button id="downloadCardBtn">Download Card</button>
+                    <button id="dailyBtn">📅 Today's Cookie</button>
                 </div>
                 <div class="fortune" id="fortune">Click the button to reveal your fortune...</div>
+                <div class="fortune-extras" id="fortuneExtras"></div>
             </div>
         </main>
 
@@ -623,28 +1539,165 @@ async fn index() -> HttpResponse {
             }
         }
 
+        // Shows the lucky numbers, mood and tips that come with structured fortunes.
+        // Also says when the oracle is resting because today's budget is spent.
+        function showExtras(data) {
+            const extras = document.getElementById('fortuneExtras');
+            if (!data) { extras.innerHTML = ''; return; }
+            let html = '';
+            if (data.lucky_numbers) {
+                html += `
+                    <div>${escapeHtml(data.emoji)} Mood: <strong>${escapeHtml(data.mood)}</strong></div>
+                    <div>🍀 Lucky numbers: ${data.lucky_numbers.join(', ')}</div>
+                    <div>💡 ${escapeHtml(data.how_to_apply)}</div>
+                `;
+            }
+            // Without a source there was no fortune, and the message already says why.
+            if (data.budget_exhausted && data.source) {
+                html += `<div>🌙 The oracle is resting until tomorrow, so this one came from the cookie jar.</div>`;
+            }
+            extras.innerHTML = html;
+        }
+
+        function finishFortune(text, data) {
+            const fortune = document.getElementById('fortune');
+            showExtras(data);
+            fortune.textContent = text;
+            fortune.classList.remove('loading');
+            saveFortune(text);
+
+            // Update streak
+            const newStreak = updateStreak();
+            document.getElementById('streak-count').textContent = newStreak;
+        }
+
+        // Query string for the vibe and chosen category and persona.
+        function fortuneQuery(vibe) {
+            const params = new URLSearchParams({ extra: vibe });
+            const category = document.getElementById('categorySelect').value;
+            if (category) params.set('category', category);
+            const persona = document.getElementById('personaSelect').value;
+            if (persona) params.set('persona', persona);
+            return params.toString();
+        }
+
+        // Fills a dropdown from the server's presets (categories or personas).
+        async function loadPresets(selectId, url) {
+            const select = document.getElementById(selectId);
+            try {
+                const response = await fetch(url);
+                const presets = await response.json();
+                for (const preset of presets) {
+                    const option = document.createElement('option');
+                    option.value = preset.name;
+                    option.textContent = preset.label;
+                    select.appendChild(option);
+                }
+            } catch (error) {
+                // Without presets the defaults still work.
+            }
+            select.hidden = select.options.length <= 1;
+        }
+
         async function getFortune() {
             const fortune = document.getElementById('fortune');
             const vibe = document.getElementById('vibeInput').value || '';
             
             fortune.textContent = 'Loading your fortune...';
             fortune.classList.add('loading');
+            showExtras(null);
             
             try {
-                const response = await fetch('api/fortune?extra=' + encodeURIComponent(vibe));
+                const response = await fetch('api/fortune?' + fortuneQuery(vibe));
                 const data = await response.json();
-                fortune.textContent = data.fortune;
+                finishFortune(data.fortune, data);
+            } catch (error) {
+                fortune.textContent = 'The cookie is too shy to speak...';
                 fortune.classList.remove('loading');
-                saveFortune(data.fortune);
-                
-                // Update streak
-                const newStreak = updateStreak();
-                document.getElementById('streak-count').textContent = newStreak;
+            }
+        }
+
+        // Everyone gets the same cookie today, so there's something to talk about.
+        async function getDailyFortune() {
+            const fortune = document.getElementById('fortune');
+            fortune.textContent = "Fetching today's cookie...";
+            fortune.classList.add('loading');
+            showExtras(null);
+
+            try {
+                const response = await fetch('api/fortune/daily');
+                const data = await response.json();
+                finishFortune(data.fortune, data);
             } catch (error) {
                 fortune.textContent = 'The cookie is too shy to speak...';
                 fortune.classList.remove('loading');
             }
         }
+
+        // Streams the fortune over SSE and reveals it word by word.
+        // Falls back to the plain request if the stream can't be opened.
+        function streamFortune() {
+            if (!window.EventSource) { getFortune(); return; }
+            const fortune = document.getElementById('fortune');
+            const vibe = document.getElementById('vibeInput').value || '';
+
+            fortune.textContent = 'Loading your fortune...';
+            fortune.classList.add('loading');
+            showExtras(null);
+
+            const source = new EventSource('api/fortune/stream?' + fortuneQuery(vibe));
+            let received = '';
+            let shown = 0;
+            let words = [];
+            let started = false;
+            let finished = false;
+            let done = null;
+            let meta = {};
+
+            const timer = setInterval(() => {
+                if (shown < words.length) {
+                    if (shown === 0) { fortune.textContent = ''; fortune.classList.remove('loading'); }
+                    fortune.textContent += (shown > 0 ? ' ' : '') + words[shown];
+                    shown++;
+                } else if (finished) {
+                    clearInterval(timer);
+                    finishFortune(done && done.fortune ? done.fortune : received.trim(), { ...meta, ...done });
+                }
+            }, 70);
+
+            // Only reveal words that are complete; the last one may still be growing.
+            const queueWords = (final) => {
+                const parts = received.trim().split(/\s+/).filter(Boolean);
+                words = final || /\s$/.test(received) ? parts : parts.slice(0, -1);
+            };
+
+            source.addEventListener('meta', (e) => { started = true; meta = JSON.parse(e.data); });
+            source.addEventListener('token', (e) => {
+                received += JSON.parse(e.data).text;
+                queueWords(false);
+            });
+            source.addEventListener('done', (e) => {
+                source.close();
+                if (e.data) done = JSON.parse(e.data);
+                queueWords(true);
+                finished = true;
+            });
+            source.addEventListener('error', (e) => {
+                source.close();
+                if (e.data) {
+                    clearInterval(timer);
+                    const message = JSON.parse(e.data).fortune;
+                    fortune.textContent = received ? received.trim() : message;
+                    fortune.classList.remove('loading');
+                } else if (!started) {
+                    clearInterval(timer);
+                    getFortune();
+                } else {
+                    queueWords(true);
+                    finished = true;
+                }
+            });
+        }
                 // Favorites handling
                 function getFavorites() {
                     const data = localStorage.getItem(FAVORITES_KEY);
@@ -804,11 +1857,12 @@ async fn index() -> HttpResponse {
             const el = document.getElementById('cookie');
             el.classList.add('crack');
             setTimeout(() => el.classList.remove('crack'), 600);
-            getFortune();
+            streamFortune();
         });
         document.getElementById('copyBtn').addEventListener('click', copyFortune);
         document.getElementById('favBtn').addEventListener('click', toggleFavoriteCurrent);
         document.getElementById('downloadCardBtn').addEventListener('click', downloadFortuneCard);
+        document.getElementById('dailyBtn').addEventListener('click', getDailyFortune);
         document.getElementById('searchInput').addEventListener('input', (e) => {
             const q = e.target.value || '';
             updateHistoryDisplay(q);
@@ -819,6 +1873,8 @@ async fn index() -> HttpResponse {
         updateHistoryDisplay();
         updateFavoritesDisplay();
         initTheme();
+        loadPresets('categorySelect', 'api/categories');
+        loadPresets('personaSelect', 'api/personas');
         displayStreak();
 
         // Keyboard shortcuts: c (copy), f (favorite)
@@ -838,23 +1894,209 @@ async fn index() -> HttpResponse {
         .body(html)
 }
 
+/// Loads `FORTUNE_CONFIG`, or `fortune.toml` if it exists. The environment
+/// and `.env` take precedence over the file.
+fn load_config_file() -> io::Result<()> {
+    let (path, required) = match std::env::var_os("FORTUNE_CONFIG") {
+        Some(path) => (PathBuf::from(path), true),
+        None => (PathBuf::from(config::DEFAULT_CONFIG_FILE), false),
+    };
+    if !required && !path.exists() {
+        return Ok(());
+    }
+    let applied = config::load_file(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot read config file {}: {}", path.display(), e)))?;
+    println!("⚙️  Loaded {} settings from {}", applied, path.display());
+    Ok(())
+}
+
+/// Warns at startup if the configured Gemini model isn't one the key can use.
+async fn check_gemini_model(config: &Config, client: &reqwest::Client) {
+    let Ok(gemini) = GeminiProvider::from_config(config, client) else {
+        return;
+    };
+    match gemini.list_models().await {
+        Ok(models) if models.iter().any(|model| model == gemini.model()) => {
+            println!("✅ Gemini model {} is available", gemini.model());
+        }
+        Ok(models) => eprintln!(
+            "⚠️  Gemini model {} isn't among the {} models this key can use: {}",
+            gemini.model(),
+            models.len(),
+            models.join(", ")
+        ),
+        Err(e) => eprintln!("⚠️  Couldn't list Gemini models to check {}: {}", gemini.model(), e),
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    
-    let port = env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .unwrap_or(8080);
-    
+    load_config_file()?;
+
+    let config = Config::from_env();
+    let port = config.port;
+    let prompt = Template::parse(&config.prompt)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid PROMPT template: {}", e)))?;
+    let categories = config
+        .categories
+        .iter()
+        .map(|category| {
+            let prompt = Template::parse(&category.prompt).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid prompt template for category {}: {}", category.name, e),
+                )
+            })?;
+            Ok((category.clone(), prompt))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let locales = config
+        .locales
+        .iter()
+        .map(|locale| {
+            let prompt = locale
+                .prompt
+                .as_deref()
+                .map(Template::parse)
+                .transpose()
+                .map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid prompt template for locale {}: {}", locale.code, e),
+                    )
+                })?;
+            Ok((locale.clone(), prompt))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let client = config
+        .http
+        .build_client()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("cannot build HTTP client: {}", e)))?;
+
+    if config.gemini_check_model && config.providers.iter().any(|name| name == "gemini") {
+        check_gemini_model(&config, &client).await;
+    }
+
+    // The providers hold clones of this client, so they all draw on one pool.
+    let chain = provider::chain_from_config(&config, &client)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    println!("🔗 Provider chain: {}", chain.names().join(" → "));
+
+    let files: Option<Arc<dyn FortuneProvider>> = match &config.fortune_dir {
+        Some(dir) => {
+            let files = FortuneFileProvider::load(dir).map_err(|e| {
+                io::Error::new(e.kind(), format!("cannot read FORTUNE_DIR {}: {}", dir.display(), e))
+            })?;
+            println!("📜 Loaded {} fortunes from {}", files.len(), dir.display());
+            Some(Arc::new(files))
+        }
+        None => None,
+    };
+
     println!("🥠 Digital Fortune Cookie Server running on http://127.0.0.1:{}/demos/digital-fortune-cookie/", port);
-    
-    HttpServer::new(|| {
+
+    let pool = (config.pool_size > 0).then(|| FortunePool::new(config.pool_size));
+    let recent = RecentFortunes::new(config.dedup_window, config.dedup_threshold);
+    let daily = DailyCache::new(config.daily_cache_size);
+    let vibe_guard = VibeGuard::new(config.vibe_max_chars, &config.vibe_deny_list, config.vibe_injection_check);
+    let state = web::Data::new(AppState {
+        config,
+        prompt,
+        categories,
+        vibe_guard,
+        locales,
+        chain,
+        files,
+        pool,
+        recent,
+        daily,
+    });
+    if state.pool.is_some() {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let Some(pool) = &state.pool else { return };
+            let options = &state.config.generation;
+            // Rendered per fortune, so dates and times in the template stay current.
+            let prompt = || {
+                let vars = PromptVars::now(None, &state.config.locale);
+                build_prompt(&state.render(None, &state.locales[0], &vars), options)
+            };
+            // Corpus fortunes are instant anyway; only pool them if the
+            // corpus is all there is.
+            let skip = (state.chain.primary() != "corpus").then_some("corpus");
+            let retry_delay = Duration::from_secs(state.config.pool_retry_secs);
+            pool.refill(&state.chain, prompt, options, skip, retry_delay).await;
+        });
+    }
+    HttpServer::new(move || {
         App::new()
+            .app_data(state.clone())
             .route("/demos/digital-fortune-cookie/", web::get().to(index))
             .route("/demos/digital-fortune-cookie/api/fortune", web::get().to(get_fortune))
+            .route("/demos/digital-fortune-cookie/api/fortune/stream", web::get().to(get_fortune_stream))
+            .route("/demos/digital-fortune-cookie/api/fortune/daily", web::get().to(get_daily_fortune))
+            .route("/demos/digital-fortune-cookie/api/fortunes", web::get().to(get_fortunes))
+            .route("/demos/digital-fortune-cookie/api/status", web::get().to(get_status))
+            .route("/demos/digital-fortune-cookie/api/stats", web::get().to(get_stats))
+            .route("/demos/digital-fortune-cookie/api/categories", web::get().to(get_categories))
+            .route("/demos/digital-fortune-cookie/api/personas", web::get().to(get_personas))
     })
     .bind(("127.0.0.1", port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locales(codes: &[&str]) -> Vec<(Locale, Option<Template>)> {
+        codes
+            .iter()
+            .map(|code| {
+                let locale = Locale {
+                    code: code.to_string(),
+                    language: code.to_string(),
+                    prompt: None,
+                };
+                (locale, None)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn orders_accepted_languages_by_quality() {
+        assert_eq!(
+            accepted_languages("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.7, *;q=0.5"),
+            ["fr-CH", "fr", "en", "de"]
+        );
+        assert_eq!(
+            accepted_languages("en;q=0.5, es, pt-BR;q=0.9"),
+            ["es", "pt-BR", "en"]
+        );
+        // Equally preferred tags keep their order.
+        assert_eq!(accepted_languages("de;q=0.8,it;q=0.8"), ["de", "it"]);
+    }
+
+    #[test]
+    fn skips_refused_and_malformed_languages() {
+        assert_eq!(accepted_languages("es;q=0, en"), ["en"]);
+        assert_eq!(accepted_languages("fr;q=oops"), ["fr"]);
+        assert_eq!(accepted_languages(" , *"), Vec::<&str>::new());
+        assert!(accepted_languages("").is_empty());
+    }
+
+    #[test]
+    fn finds_locales_by_tag_then_language() {
+        let locales = locales(&["en", "es", "pt-BR"]);
+        let found = |tag| find_locale(&locales, tag).map(|(locale, _)| locale.code.as_str());
+        assert_eq!(found("ES"), Some("es"));
+        assert_eq!(found("es-MX"), Some("es"));
+        assert_eq!(found("pt-br"), Some("pt-BR"));
+        assert_eq!(found("pt-PT"), Some("pt-BR"));
+        assert_eq!(found("pt"), Some("pt-BR"));
+        assert_eq!(found("de"), None);
+    }
+}
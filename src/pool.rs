@@ -0,0 +1,76 @@
+//! A bounded buffer of ready-made fortunes, kept topped up in the background
+//! so plain cookie clicks don't have to wait on the provider.
+
+use crate::provider::{FortuneProvider, GenerateOptions, Generation};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+pub struct FortunePool {
+    ready: Mutex<VecDeque<Generation>>,
+    capacity: usize,
+    /// Wakes the refill task when a fortune is taken.
+    taken: Notify,
+}
+
+impl FortunePool {
+    pub fn new(capacity: usize) -> Self {
+        FortunePool {
+            ready: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            taken: Notify::new(),
+        }
+    }
+
+    /// Takes the oldest ready fortune, if any, and asks for a replacement.
+    pub fn pop(&self) -> Option<Generation> {
+        let generation = self.ready.lock().unwrap().pop_front();
+        self.taken.notify_one();
+        generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.ready.lock().unwrap().len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Keeps the pool full, forever, with a fresh `prompt()` for each
+    /// fortune. Fortunes from `skip_provider` (the
+    /// offline corpus, when it is only a fallback) aren't worth pooling, so
+    /// they and failures both pause refilling for `retry_delay`.
+    pub async fn refill(
+        &self,
+        provider: &dyn FortuneProvider,
+        prompt: impl Fn() -> String,
+        options: &GenerateOptions,
+        skip_provider: Option<&str>,
+        retry_delay: Duration,
+    ) {
+        loop {
+            while self.len() >= self.capacity {
+                self.taken.notified().await;
+            }
+            match provider.generate(&prompt(), options).await {
+                Ok(generation) if Some(generation.provider) != skip_provider => {
+                    self.ready.lock().unwrap().push_back(generation);
+                }
+                Ok(generation) => {
+                    eprintln!(
+                        "🫙 Fortune pool got a {} fallback; pausing refill for {}s",
+                        generation.provider,
+                        retry_delay.as_secs()
+                    );
+                    tokio::time::sleep(retry_delay).await;
+                }
+                Err(e) => {
+                    eprintln!("🫙 Fortune pool refill failed ({}); pausing for {}s", e, retry_delay.as_secs());
+                    tokio::time::sleep(retry_delay).await;
+                }
+            }
+        }
+    }
+}
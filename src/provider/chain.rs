@@ -0,0 +1,276 @@
+use super::{Batch, FortuneProvider, GenerateOptions, Generation, ProviderError, StreamingGeneration};
+use crate::breaker::{BreakerStatus, CircuitBreaker};
+use crate::budget::DailyBudget;
+use crate::usage::UsageStats;
+use async_trait::async_trait;
+use futures_util::future::ready;
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+struct Link {
+    provider: Arc<dyn FortuneProvider>,
+    breaker: CircuitBreaker,
+}
+
+/// Tries providers in order, skipping any whose circuit breaker is open and
+/// metered ones once the daily budget is spent. Every fortune it produces is
+/// counted in its usage stats.
+pub struct ProviderChain {
+    links: Vec<Link>,
+    usage: Arc<UsageStats>,
+    budget: Arc<DailyBudget>,
+}
+
+/// Why the links passed over in a request were skipped.
+#[derive(Clone, Copy, PartialEq)]
+enum Skipped {
+    None,
+    /// Only ever for the budget, so the breakers aren't to blame.
+    Budget,
+    /// At least one breaker was open.
+    Breaker,
+}
+
+impl Skipped {
+    fn and(self, reason: Skipped) -> Skipped {
+        if self == Skipped::Breaker || reason == Skipped::Breaker {
+            Skipped::Breaker
+        } else {
+            reason
+        }
+    }
+
+    /// The error for a request no provider was called for.
+    fn error(self) -> ProviderError {
+        match self {
+            Skipped::Budget => ProviderError::BudgetExhausted,
+            Skipped::None | Skipped::Breaker => ProviderError::Unavailable,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkStatus {
+    pub provider: &'static str,
+    #[serde(flatten)]
+    pub breaker: BreakerStatus,
+}
+
+impl ProviderChain {
+    pub fn new(
+        providers: Vec<Arc<dyn FortuneProvider>>,
+        failure_threshold: u32,
+        cooldown: Duration,
+        budget: DailyBudget,
+    ) -> Self {
+        ProviderChain {
+            links: providers
+                .into_iter()
+                .map(|provider| Link {
+                    provider,
+                    breaker: CircuitBreaker::new(failure_threshold, cooldown),
+                })
+                .collect(),
+            usage: Arc::new(UsageStats::new()),
+            budget: Arc::new(budget),
+        }
+    }
+
+    pub fn usage(&self) -> &UsageStats {
+        &self.usage
+    }
+
+    pub fn budget(&self) -> &DailyBudget {
+        &self.budget
+    }
+
+    /// Takes `link`'s breaker permit and, if it's metered, reserves up to
+    /// `calls` requests from the budget, returning how many calls may be made.
+    /// The budget is checked first so a skipped provider doesn't use up its
+    /// breaker's probe; a reservation the breaker turns away is handed back.
+    fn admit(&self, link: &Link, calls: u64) -> Result<u64, Skipped> {
+        let metered = link.provider.metered();
+        let reserved = if metered { self.budget.reserve(calls) } else { calls };
+        if reserved == 0 {
+            return Err(Skipped::Budget);
+        }
+        if !link.breaker.try_acquire() {
+            if metered {
+                self.budget.settle(reserved, 0);
+            }
+            return Err(Skipped::Breaker);
+        }
+        Ok(reserved)
+    }
+
+    /// Counts a batch from `link`: squares the requests reserved for it with
+    /// the upstream calls made, and counts each fortune in the usage stats and
+    /// against the token budget.
+    fn count_batch(&self, link: &Link, batch: &Batch, reserved: u64) {
+        if link.provider.metered() {
+            self.budget.settle(reserved, batch.calls as u64);
+        }
+        for generation in &batch.generations {
+            self.usage.record(generation.provider, &generation.model, generation.usage);
+            if link.provider.metered() {
+                self.budget.charge_tokens(generation.usage);
+            }
+        }
+        for (model, usage) in &batch.unclaimed_usage {
+            self.usage.record_unclaimed(link.provider.name(), model, *usage);
+            if link.provider.metered() {
+                self.budget.charge_tokens(Some(*usage));
+            }
+        }
+    }
+
+    /// Counts a streamed fortune's usage once its last chunk has been read.
+    /// A stream abandoned partway isn't counted, though a metered one still
+    /// costs the request reserved for it.
+    fn recorded(&self, link: &Link, generation: StreamingGeneration) -> StreamingGeneration {
+        let stats = self.usage.clone();
+        let budget = link.provider.metered().then(|| self.budget.clone());
+        let usage = generation.usage.clone();
+        let provider = generation.provider;
+        let model = generation.model.clone();
+        let record = stream::once(async move {
+            let usage = *usage.lock().unwrap();
+            stats.record(provider, &model, usage);
+            if let Some(budget) = budget {
+                budget.charge_tokens(usage);
+            }
+        })
+        .filter_map(|()| ready(None));
+        StreamingGeneration {
+            chunks: generation.chunks.chain(record).boxed(),
+            ..generation
+        }
+    }
+
+    /// Name of the first provider; anything else answering is a fallback.
+    pub fn primary(&self) -> &'static str {
+        self.links.first().map_or("none", |link| link.provider.name())
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.links.iter().map(|link| link.provider.name()).collect()
+    }
+
+    pub fn status(&self) -> Vec<LinkStatus> {
+        self.links
+            .iter()
+            .map(|link| LinkStatus {
+                provider: link.provider.name(),
+                breaker: link.breaker.status(),
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl FortuneProvider for ProviderChain {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    /// A batch of one, so that a call whose fortune was blocked still has its
+    /// tokens counted.
+    async fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError> {
+        let generations = self.generate_batch(prompt, options, 1).await.into_result()?;
+        generations.into_iter().next().ok_or(ProviderError::Empty)
+    }
+
+    /// Fails over like `generate`, but only until a provider starts streaming;
+    /// an error mid-stream ends that fortune rather than switching providers.
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<StreamingGeneration, ProviderError> {
+        let mut first_error = None;
+        let mut skipped = Skipped::None;
+        for link in &self.links {
+            if let Err(reason) = self.admit(link, 1) {
+                skipped = skipped.and(reason);
+                continue;
+            }
+            match link.provider.generate_stream(prompt, options).await {
+                Ok(generation) => {
+                    link.breaker.record_success();
+                    return Ok(self.recorded(link, generation));
+                }
+                Err(e) if e.is_refusal() => {
+                    eprintln!("{} provider declined: {}", link.provider.name(), e);
+                    link.breaker.record_success();
+                    return Err(e);
+                }
+                Err(e) => {
+                    eprintln!("Error streaming from {} provider: {}", link.provider.name(), e);
+                    link.breaker.record_failure();
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or_else(|| skipped.error()))
+    }
+
+    /// Fills the batch from each provider in turn, so a short batch from one
+    /// is topped up by the next. A metered provider is asked for no more
+    /// fortunes than the requests it could reserve from the budget can produce.
+    async fn generate_batch(&self, prompt: &str, options: &GenerateOptions, count: usize) -> Batch {
+        let mut batch = Batch::default();
+        let mut skipped = Skipped::None;
+        for link in &self.links {
+            let wanted = count.saturating_sub(batch.generations.len());
+            if wanted == 0 {
+                break;
+            }
+            let per_call = link.provider.fortunes_per_call().max(1);
+            let reserved = match self.admit(link, wanted.div_ceil(per_call) as u64) {
+                Ok(reserved) => reserved,
+                Err(reason) => {
+                    skipped = skipped.and(reason);
+                    continue;
+                }
+            };
+            let wanted = wanted.min((reserved as usize).saturating_mul(per_call));
+            let part = link.provider.generate_batch(prompt, options, wanted).await;
+            self.count_batch(link, &part, reserved);
+            // The breaker judges this provider by its own fortunes, not by
+            // what earlier ones already gathered.
+            let produced = !part.generations.is_empty();
+            batch.calls += part.calls;
+            batch.generations.extend(part.generations);
+            batch.unclaimed_usage.extend(part.unclaimed_usage);
+            match part.error {
+                Some(e) if e.is_refusal() && !produced => {
+                    eprintln!("{} provider declined: {}", link.provider.name(), e);
+                    link.breaker.record_success();
+                    batch.error.get_or_insert(e);
+                    break;
+                }
+                Some(e) if !produced => {
+                    eprintln!("Error calling {} provider: {}", link.provider.name(), e);
+                    link.breaker.record_failure();
+                    batch.error.get_or_insert(e);
+                }
+                error => {
+                    link.breaker.record_success();
+                    if let Some(e) = error {
+                        batch.error.get_or_insert(e);
+                    }
+                }
+            }
+        }
+        if batch.generations.is_empty() {
+            batch.error.get_or_insert_with(|| skipped.error());
+        }
+        batch
+    }
+}
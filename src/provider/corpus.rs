@@ -0,0 +1,54 @@
+use super::{FortuneProvider, GenerateOptions, Generation, ProviderError};
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::SeedableRng;
+
+/// Curated advice compiled into the binary, one fortune per line.
+const BUNDLED: &str = include_str!("../../fortunes/bizarre.txt");
+
+/// Serves fortunes from the bundled corpus. Needs no network or API key, so it
+/// backs up every other provider.
+pub struct CorpusProvider {
+    fortunes: Vec<&'static str>,
+}
+
+impl CorpusProvider {
+    pub fn bundled() -> Self {
+        CorpusProvider {
+            fortunes: BUNDLED
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl FortuneProvider for CorpusProvider {
+    fn name(&self) -> &'static str {
+        "corpus"
+    }
+
+    async fn generate(
+        &self,
+        _prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError> {
+        // A seed picks the same line every time, like it would on a model.
+        let text = match options.seed {
+            Some(seed) => self.fortunes.choose(&mut StdRng::seed_from_u64(seed as u64)),
+            None => self.fortunes.choose(&mut rand::rng()),
+        }
+        .ok_or(ProviderError::Empty)?;
+
+        Ok(Generation {
+            text: text.to_string(),
+            provider: self.name(),
+            model: "bundled".to_string(),
+            truncated: false,
+            usage: None,
+        })
+    }
+}
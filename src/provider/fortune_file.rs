@@ -0,0 +1,49 @@
+use super::{FortuneProvider, GenerateOptions, Generation, ProviderError};
+use crate::strfile::{self, FileFortune};
+use async_trait::async_trait;
+use rand::seq::IndexedRandom;
+use std::io;
+use std::path::Path;
+
+/// Serves random entries from classic `fortune(6)` databases on disk.
+pub struct FortuneFileProvider {
+    fortunes: Vec<FileFortune>,
+}
+
+impl FortuneFileProvider {
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        Ok(FortuneFileProvider {
+            fortunes: strfile::load_dir(dir)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.fortunes.len()
+    }
+}
+
+#[async_trait]
+impl FortuneProvider for FortuneFileProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn generate(
+        &self,
+        _prompt: &str,
+        _options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError> {
+        let fortune = self
+            .fortunes
+            .choose(&mut rand::rng())
+            .ok_or(ProviderError::Empty)?;
+
+        Ok(Generation {
+            text: fortune.text.clone(),
+            provider: self.name(),
+            model: fortune.database.clone(),
+            truncated: false,
+            usage: None,
+        })
+    }
+}
@@ -0,0 +1,561 @@
+use super::{
+    sse, Batch, FortuneProvider, GenerateOptions, Generation, ProviderError, RetryPolicy,
+    StreamingGeneration, Usage, UsageCell,
+};
+use crate::config::Config;
+use crate::structured;
+use async_trait::async_trait;
+use futures_util::future;
+use futures_util::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Most candidates Gemini returns from one request.
+const MAX_CANDIDATES: usize = 8;
+
+/// Finish reasons that mean a safety or policy filter stopped the response.
+const BLOCKING_FINISH_REASONS: &[&str] = &[
+    "SAFETY",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+    "IMAGE_SAFETY",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Part {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    contents: Vec<ContentBlock>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<ContentBlock>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    safety_settings: Vec<SafetySetting>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Debug, Serialize)]
+struct ContentBlock {
+    parts: Vec<TextPart>,
+}
+
+#[derive(Debug, Serialize)]
+struct TextPart {
+    text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SafetySetting {
+    category: String,
+    threshold: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidate_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<Value>,
+}
+
+impl GenerationConfig {
+    fn from_options(options: &GenerateOptions, candidates: usize) -> Option<Self> {
+        let config = GenerationConfig {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_output_tokens: options.max_output_tokens,
+            seed: options.seed,
+            candidate_count: (candidates > 1).then_some(candidates),
+            response_mime_type: options.structured.then_some("application/json"),
+            response_schema: options.structured.then(response_schema),
+        };
+        let empty = config.temperature.is_none()
+            && config.top_p.is_none()
+            && config.max_output_tokens.is_none()
+            && config.seed.is_none()
+            && config.candidate_count.is_none()
+            && config.response_schema.is_none();
+        (!empty).then_some(config)
+    }
+}
+
+/// The structured fortune schema in Gemini's OpenAPI dialect: upper-case
+/// type names, no numeric bounds on arrays, and an explicit property order.
+fn response_schema() -> Value {
+    fn convert(schema: &Value) -> Value {
+        let Value::Object(fields) = schema else {
+            return schema.clone();
+        };
+        let mut converted = serde_json::Map::new();
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("type", Value::String(name)) => {
+                    converted.insert(key.clone(), Value::String(name.to_uppercase()));
+                }
+                ("properties", Value::Object(properties)) => {
+                    let properties = properties
+                        .iter()
+                        .map(|(name, property)| (name.clone(), convert(property)))
+                        .collect();
+                    converted.insert(key.clone(), Value::Object(properties));
+                }
+                ("items", items) => {
+                    converted.insert(key.clone(), convert(items));
+                }
+                ("minItems" | "maxItems" | "minimum" | "maximum", _) => {}
+                _ => {
+                    converted.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Value::Object(converted)
+    }
+
+    let mut schema = convert(&structured::json_schema());
+    if let Value::Object(fields) = &mut schema {
+        fields.insert("propertyOrdering".to_string(), structured::FIELD_ORDER.into());
+    }
+    schema
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    /// Thinking models bill their reasoning as output tokens too.
+    #[serde(default)]
+    thoughts_token_count: u64,
+    #[serde(default)]
+    total_token_count: u64,
+}
+
+impl From<&UsageMetadata> for Usage {
+    fn from(metadata: &UsageMetadata) -> Self {
+        Usage {
+            prompt_tokens: metadata.prompt_token_count,
+            output_tokens: metadata.candidates_token_count + metadata.thoughts_token_count,
+            total_tokens: metadata.total_token_count,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<Content>,
+    finish_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SafetyRating {
+    category: String,
+    probability: String,
+    #[serde(default)]
+    blocked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelList {
+    #[serde(default)]
+    models: Vec<ModelInfo>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelInfo {
+    name: String,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+/// `gemini-2.5-flash` from either `gemini-2.5-flash` or `models/gemini-2.5-flash`.
+fn model_name(model: &str) -> &str {
+    model.trim().trim_start_matches("models/")
+}
+
+fn models_url(base_url: &str) -> String {
+    format!("{}/models", base_url.trim_end_matches('/'))
+}
+
+/// What one `generateContent` request produced: a result per candidate,
+/// and the usage of the request as a whole.
+struct Candidates {
+    results: Vec<Result<Generation, ProviderError>>,
+    usage: Option<Usage>,
+}
+
+/// Google Gemini `generateContent` backend.
+pub struct GeminiProvider {
+    api_key: String,
+    model: String,
+    models_url: String,
+    api_url: String,
+    stream_url: String,
+    safety_settings: Vec<SafetySetting>,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    read_timeout: Duration,
+}
+
+impl GeminiProvider {
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Names of the models the configured key can generate content with.
+    pub async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let mut names = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(&self.models_url)
+                .header("x-goog-api-key", &self.api_key)
+                .query(&[("pageSize", "1000")]);
+            if let Some(token) = &page_token {
+                request = request.query(&[("pageToken", token)]);
+            }
+            let response = request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(ProviderError::Request)?;
+            let page = response.json::<ModelList>().await.map_err(ProviderError::body)?;
+
+            names.extend(
+                page.models
+                    .into_iter()
+                    .filter(|model| model.supported_generation_methods.iter().any(|m| m == "generateContent"))
+                    .map(|model| model_name(&model.name).to_string()),
+            );
+            match page.next_page_token.filter(|token| !token.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => return Ok(names),
+            }
+        }
+    }
+
+    /// Sends one `generateContent` request for `count` candidates. A refused
+    /// prompt fails the whole request; each candidate succeeds or fails alone.
+    async fn candidates(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+        count: usize,
+    ) -> Result<Candidates, ProviderError> {
+        let request_body = self.request_body(prompt, options, count);
+
+        let response = self
+            .retry
+            .send(self.name(), || {
+                self.client
+                    .post(&self.api_url)
+                    .header("x-goog-api-key", &self.api_key)
+                    .json(&request_body)
+            })
+            .await?;
+
+        let data = response
+            .json::<GeminiResponse>()
+            .await
+            .map_err(ProviderError::body)?;
+
+        if let Some(refusal) = data.prompt_refusal() {
+            return Err(refusal);
+        }
+        Ok(Candidates {
+            usage: data.usage_metadata.as_ref().map(Usage::from),
+            results: data
+                .candidates
+                .into_iter()
+                .map(|candidate| candidate.into_generation(self.name(), &self.model))
+                .collect(),
+        })
+    }
+
+    pub fn from_config(config: &Config, client: &reqwest::Client) -> Result<Self, ProviderError> {
+        let api_key = config.gemini_api_key.clone().ok_or_else(|| {
+            ProviderError::NotConfigured(
+                "GEMINI_API_KEY is not set; add it to your .env file".to_string(),
+            )
+        })?;
+        let safety_settings = config
+            .gemini_safety_settings
+            .iter()
+            .map(|(category, threshold)| SafetySetting {
+                category: category.clone(),
+                threshold: threshold.clone(),
+            })
+            .collect();
+        let model = model_name(&config.gemini_model).to_string();
+        let models_url = models_url(&config.gemini_base_url);
+        Ok(GeminiProvider {
+            api_key,
+            api_url: format!("{}/{}:generateContent", models_url, model),
+            stream_url: format!("{}/{}:streamGenerateContent?alt=sse", models_url, model),
+            models_url,
+            model,
+            safety_settings,
+            client: client.clone(),
+            retry: config.retry.clone(),
+            read_timeout: config.http.read_timeout,
+        })
+    }
+
+    fn request_body(&self, prompt: &str, options: &GenerateOptions, candidates: usize) -> GeminiRequest {
+        GeminiRequest {
+            contents: vec![ContentBlock {
+                parts: vec![TextPart {
+                    text: prompt.to_string(),
+                }],
+            }],
+            system_instruction: options.system.as_ref().map(|system| ContentBlock {
+                parts: vec![TextPart { text: system.clone() }],
+            }),
+            safety_settings: self.safety_settings.clone(),
+            generation_config: GenerationConfig::from_options(options, candidates),
+        }
+    }
+}
+
+impl GeminiResponse {
+    /// All text in the first candidate; streamed chunks may split it across parts.
+    fn text(&self) -> String {
+        self.candidates.first().map(Candidate::text).unwrap_or_default()
+    }
+
+    /// A blocked prompt, or a refusal in the first candidate.
+    fn refusal(&self) -> Option<ProviderError> {
+        self.prompt_refusal()
+            .or_else(|| self.candidates.first()?.refusal())
+    }
+
+    fn prompt_refusal(&self) -> Option<ProviderError> {
+        let feedback = self.prompt_feedback.as_ref()?;
+        let reason = feedback.block_reason.as_ref()?;
+        Some(ProviderError::Blocked(format!(
+            "prompt blocked ({}){}",
+            reason,
+            flagged_categories(&feedback.safety_ratings)
+        )))
+    }
+}
+
+impl Candidate {
+    fn text(&self) -> String {
+        self.content
+            .as_ref()
+            .map(|content| content.parts.iter().map(|part| part.text.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    /// A safety-stopped response or a recitation stop.
+    fn refusal(&self) -> Option<ProviderError> {
+        match self.finish_reason.as_deref()? {
+            "RECITATION" => Some(ProviderError::Recitation),
+            reason if BLOCKING_FINISH_REASONS.contains(&reason) => {
+                Some(ProviderError::Blocked(format!(
+                    "response blocked ({}){}",
+                    reason,
+                    flagged_categories(&self.safety_ratings)
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    fn into_generation(self, provider: &'static str, model: &str) -> Result<Generation, ProviderError> {
+        if let Some(refusal) = self.refusal() {
+            return Err(refusal);
+        }
+
+        let text = self.text();
+        let truncated = self.finish_reason.as_deref() == Some("MAX_TOKENS");
+        if text.trim().is_empty() {
+            return Err(if truncated {
+                ProviderError::Truncated
+            } else {
+                ProviderError::Empty
+            });
+        }
+
+        Ok(Generation {
+            text,
+            provider,
+            model: model.to_string(),
+            truncated,
+            usage: None,
+        })
+    }
+}
+
+/// Lists the categories that tripped a filter, e.g. `: HARASSMENT=HIGH`.
+fn flagged_categories(ratings: &[SafetyRating]) -> String {
+    let flagged: Vec<String> = ratings
+        .iter()
+        .filter(|rating| rating.blocked || matches!(rating.probability.as_str(), "MEDIUM" | "HIGH"))
+        .map(|rating| {
+            format!(
+                "{}={}",
+                rating.category.trim_start_matches("HARM_CATEGORY_"),
+                rating.probability
+            )
+        })
+        .collect();
+    if flagged.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", flagged.join(", "))
+    }
+}
+
+#[async_trait]
+impl FortuneProvider for GeminiProvider {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn metered(&self) -> bool {
+        true
+    }
+
+    fn fortunes_per_call(&self) -> usize {
+        MAX_CANDIDATES
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError> {
+        let candidates = self.candidates(prompt, options, 1).await?;
+        let generation = candidates.results.into_iter().next().unwrap_or(Err(ProviderError::Empty))?;
+        Ok(Generation {
+            usage: candidates.usage,
+            ..generation
+        })
+    }
+
+    /// Asks for several candidates per request, running one request per
+    /// `MAX_CANDIDATES` fortunes in parallel.
+    async fn generate_batch(&self, prompt: &str, options: &GenerateOptions, count: usize) -> Batch {
+        let requests = (0..count)
+            .step_by(MAX_CANDIDATES)
+            .map(|start| self.candidates(prompt, options, (count - start).min(MAX_CANDIDATES)));
+        let mut batch = Batch::default();
+        for outcome in future::join_all(requests).await {
+            batch.calls += 1;
+            match outcome {
+                Ok(mut candidates) => {
+                    // Usage covers the whole request, so it goes on the first
+                    // fortune, or is kept apart if there is none.
+                    match candidates.results.iter_mut().find_map(|result| result.as_mut().ok()) {
+                        Some(generation) => generation.usage = candidates.usage,
+                        None => batch
+                            .unclaimed_usage
+                            .extend(candidates.usage.map(|usage| (self.model.clone(), usage))),
+                    }
+                    candidates.results.into_iter().for_each(|candidate| batch.keep(candidate));
+                }
+                Err(e) => batch.keep(Err(e)),
+            }
+        }
+        batch
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<StreamingGeneration, ProviderError> {
+        let request_body = self.request_body(prompt, options, 1);
+
+        let response = self
+            .retry
+            .send(self.name(), || {
+                self.client
+                    .post(&self.stream_url)
+                    .header("x-goog-api-key", &self.api_key)
+                    .json(&request_body)
+            })
+            .await?;
+
+        let usage = UsageCell::default();
+        let chunk_usage = usage.clone();
+        let chunks = sse::data_events(response, self.read_timeout).filter_map(move |event| {
+            let usage = chunk_usage.clone();
+            async move {
+                let chunk = match event.and_then(|data| {
+                    serde_json::from_str::<GeminiResponse>(&data).map_err(ProviderError::Decode)
+                }) {
+                    Ok(chunk) => chunk,
+                    Err(e) => return Some(Err(e)),
+                };
+                // Each report is cumulative; the last one is the total.
+                if let Some(metadata) = &chunk.usage_metadata {
+                    *usage.lock().unwrap() = Some(metadata.into());
+                }
+                if let Some(refusal) = chunk.refusal() {
+                    return Some(Err(refusal));
+                }
+                let text = chunk.text();
+                (!text.is_empty()).then_some(Ok(text))
+            }
+        });
+
+        Ok(StreamingGeneration {
+            provider: self.name(),
+            model: self.model.clone(),
+            chunks: chunks.boxed(),
+            usage,
+        })
+    }
+}
@@ -0,0 +1,113 @@
+use super::{FortuneProvider, GenerateOptions, Generation, ProviderError, RetryPolicy, Usage};
+use crate::config::Config;
+use crate::structured;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest<'a> {
+    prompt: &'a str,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_schema: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    content: String,
+    model: Option<String>,
+    /// Set when generation stopped at `n_predict`.
+    #[serde(default)]
+    stopped_limit: bool,
+    tokens_evaluated: Option<u64>,
+    tokens_predicted: Option<u64>,
+}
+
+/// A llama.cpp `llama-server` instance (`/completion`).
+pub struct LlamaCppProvider {
+    url: String,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl LlamaCppProvider {
+    pub fn from_config(config: &Config, client: &reqwest::Client) -> Result<Self, ProviderError> {
+        Ok(LlamaCppProvider {
+            url: format!("{}/completion", config.llamacpp_base_url.trim_end_matches('/')),
+            client: client.clone(),
+            retry: config.retry.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl FortuneProvider for LlamaCppProvider {
+    fn name(&self) -> &'static str {
+        "llamacpp"
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError> {
+        // The completion endpoint has no system role, so the instruction
+        // leads the prompt.
+        let prompt = match &options.system {
+            Some(system) => format!("{}\n\n{}", system, prompt),
+            None => prompt.to_string(),
+        };
+        let request_body = CompletionRequest {
+            prompt: &prompt,
+            stream: false,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            n_predict: options.max_output_tokens,
+            seed: options.seed,
+            json_schema: options.structured.then(structured::json_schema),
+        };
+
+        let response = self
+            .retry
+            .send(self.name(), || self.client.post(&self.url).json(&request_body))
+            .await?;
+
+        let data = response
+            .json::<CompletionResponse>()
+            .await
+            .map_err(ProviderError::body)?;
+
+        if data.content.trim().is_empty() {
+            return Err(if data.stopped_limit {
+                ProviderError::Truncated
+            } else {
+                ProviderError::Empty
+            });
+        }
+
+        Ok(Generation {
+            text: data.content,
+            provider: self.name(),
+            // llama-server serves whatever single model it was started with.
+            model: data.model.unwrap_or_else(|| "local".to_string()),
+            truncated: data.stopped_limit,
+            usage: match (data.tokens_evaluated, data.tokens_predicted) {
+                (Some(prompt_tokens), Some(output_tokens)) => Some(Usage {
+                    prompt_tokens,
+                    output_tokens,
+                    total_tokens: prompt_tokens + output_tokens,
+                }),
+                _ => None,
+            },
+        })
+    }
+}
@@ -0,0 +1,377 @@
+mod chain;
+mod corpus;
+mod fortune_file;
+mod gemini;
+mod llamacpp;
+mod ollama;
+mod openai;
+mod retry;
+mod sse;
+
+pub use chain::{LinkStatus, ProviderChain};
+pub use corpus::CorpusProvider;
+pub use fortune_file::FortuneFileProvider;
+pub use gemini::GeminiProvider;
+pub use llamacpp::LlamaCppProvider;
+pub use ollama::OllamaProvider;
+pub use openai::OpenAiProvider;
+pub use retry::RetryPolicy;
+
+use crate::budget::DailyBudget;
+use crate::config::Config;
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How many single generations a batch runs at once on providers without a
+/// native batch API.
+const BATCH_CONCURRENCY: usize = 4;
+
+/// Per-call generation knobs. Providers ignore the ones they can't express.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_output_tokens: Option<u32>,
+    pub seed: Option<i32>,
+    /// Ask for JSON matching `structured::json_schema`, where supported.
+    pub structured: bool,
+    /// System instruction setting the fortune's voice, sent apart from the
+    /// prompt where the provider allows.
+    pub system: Option<String>,
+}
+
+impl GenerateOptions {
+    /// Layers `overrides` on top of these options, field by field.
+    pub fn merged(&self, overrides: &GenerateOptions) -> GenerateOptions {
+        GenerateOptions {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_output_tokens: overrides.max_output_tokens.or(self.max_output_tokens),
+            seed: overrides.seed.or(self.seed),
+            structured: self.structured,
+            system: overrides.system.clone().or_else(|| self.system.clone()),
+        }
+    }
+
+    /// The options for re-roll `attempt`. A fixed seed is moved on so the
+    /// re-roll isn't the same fortune again; attempt 0 keeps it.
+    pub fn reroll(&self, attempt: u32) -> GenerateOptions {
+        GenerateOptions {
+            seed: self.seed.map(|seed| seed.wrapping_add_unsigned(attempt)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Tokens a generation consumed, as reported by the provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.output_tokens += other.output_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+/// A finished fortune along with where it came from.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub text: String,
+    pub provider: &'static str,
+    pub model: String,
+    /// The output limit cut the fortune short.
+    pub truncated: bool,
+    /// `None` when the provider doesn't report usage, or when this fortune
+    /// shared a request whose usage was counted against another.
+    pub usage: Option<Usage>,
+}
+
+/// The fortunes a batch produced, and what producing them took.
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub generations: Vec<Generation>,
+    /// Upstream calls made, whether or not their fortunes came through. Each
+    /// is one request against the daily budget.
+    pub calls: usize,
+    /// The first failure, when fortunes are missing.
+    pub error: Option<ProviderError>,
+    /// Tokens spent by calls that produced no fortune to carry them, as when
+    /// every candidate was blocked, by model.
+    pub unclaimed_usage: Vec<(String, Usage)>,
+}
+
+impl Batch {
+    /// Keeps a fortune, or the failure if it's the first.
+    fn keep(&mut self, result: Result<Generation, ProviderError>) {
+        match result {
+            Ok(generation) => self.generations.push(generation),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+    }
+
+    /// The fortunes, or the reason there are none.
+    pub fn into_result(self) -> Result<Vec<Generation>, ProviderError> {
+        if self.generations.is_empty() {
+            return Err(self.error.unwrap_or(ProviderError::Empty));
+        }
+        Ok(self.generations)
+    }
+}
+
+/// Explainable ways a generation can end other than a clean stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Safety filters blocked the prompt or the response.
+    Blocked,
+    /// The output limit was reached before the fortune was finished.
+    Truncated,
+    /// The model stopped because it was reproducing existing text.
+    Recitation,
+}
+
+/// Text chunks of a fortune, in order, as the provider produces them.
+pub type ChunkStream = BoxStream<'static, Result<String, ProviderError>>;
+
+/// Filled in by a stream once the provider reports usage, usually at the end.
+pub type UsageCell = Arc<Mutex<Option<Usage>>>;
+
+/// A fortune that is still being generated.
+pub struct StreamingGeneration {
+    pub provider: &'static str,
+    pub model: String,
+    pub chunks: ChunkStream,
+    pub usage: UsageCell,
+}
+
+impl From<Generation> for StreamingGeneration {
+    /// A finished fortune delivered as a single chunk.
+    fn from(generation: Generation) -> Self {
+        StreamingGeneration {
+            provider: generation.provider,
+            model: generation.model,
+            chunks: stream::once(async move { Ok(generation.text) }).boxed(),
+            usage: Arc::new(Mutex::new(generation.usage)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    /// The provider is selected but missing required settings (e.g. an API key).
+    NotConfigured(String),
+    /// `FORTUNE_PROVIDER` names a backend we don't know about.
+    Unknown(String),
+    /// The upstream request could not be sent or completed.
+    Request(reqwest::Error),
+    /// The upstream stopped sending partway through a response.
+    Timeout,
+    /// The upstream answered with an error status, after any retries.
+    Status(reqwest::StatusCode, String),
+    /// The upstream answered with something we couldn't decode.
+    Parse(reqwest::Error),
+    /// A streamed event carried JSON we couldn't decode.
+    Decode(serde_json::Error),
+    /// The upstream answered, but without any text.
+    Empty,
+    /// Every provider in the chain is currently tripped.
+    Unavailable,
+    /// Every provider in the chain is metered and today's budget is spent.
+    BudgetExhausted,
+    /// Safety filters refused the prompt or the response; says which and why.
+    Blocked(String),
+    /// The model stopped because it was reciting existing material.
+    Recitation,
+    /// The output limit was hit before any text was produced.
+    Truncated,
+}
+
+impl ProviderError {
+    /// An error reading a response body: a read that timed out, or a body
+    /// that couldn't be decoded.
+    pub fn body(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            ProviderError::Timeout
+        } else {
+            ProviderError::Parse(e)
+        }
+    }
+
+    /// The in-character message shown in the cookie when generation fails.
+    pub fn fortune_message(&self) -> &'static str {
+        match self {
+            ProviderError::NotConfigured(_) | ProviderError::Unknown(_) => {
+                "API key not configured. Please add GEMINI_API_KEY to your .env file."
+            }
+            ProviderError::Status(status, _) if *status == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                "The spirits are overwhelmed right now. Ask again in a moment."
+            }
+            ProviderError::Request(_)
+            | ProviderError::Status(..)
+            | ProviderError::Timeout
+            | ProviderError::Unavailable => {
+                "Connection to the cosmic realm failed."
+            }
+            ProviderError::BudgetExhausted => "The oracle is resting until tomorrow. Come back after midnight.",
+            ProviderError::Parse(_) | ProviderError::Decode(_) => "The spirits are silent today.",
+            ProviderError::Empty => "The cookie crumbled before revealing its wisdom...",
+            ProviderError::Blocked(_) => "The cookie refuses to reveal that kind of wisdom. Try a different vibe.",
+            ProviderError::Recitation => "The cookie caught itself quoting someone else's fortune and fell silent.",
+            ProviderError::Truncated => "The cookie ran out of paper before the fortune began.",
+        }
+    }
+
+    /// The explainable outcome behind this error, if it's one of them.
+    pub fn outcome(&self) -> Option<Outcome> {
+        match self {
+            ProviderError::Blocked(_) => Some(Outcome::Blocked),
+            ProviderError::Recitation => Some(Outcome::Recitation),
+            ProviderError::Truncated => Some(Outcome::Truncated),
+            _ => None,
+        }
+    }
+
+    /// Whether the provider worked but declined this particular prompt.
+    /// Such errors say nothing about provider health, so they neither trip
+    /// breakers nor fall through to other providers.
+    pub fn is_refusal(&self) -> bool {
+        self.outcome().is_some()
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::NotConfigured(reason) => write!(f, "not configured: {}", reason),
+            ProviderError::Unknown(name) => write!(f, "unknown provider {:?}", name),
+            ProviderError::Request(e) => write!(f, "request failed: {}", e),
+            ProviderError::Timeout => write!(f, "upstream went quiet for longer than the read timeout"),
+            ProviderError::Status(status, message) if message.is_empty() => {
+                write!(f, "upstream returned {}", status)
+            }
+            ProviderError::Status(status, message) => {
+                write!(f, "upstream returned {}: {}", status, message)
+            }
+            ProviderError::Parse(e) => write!(f, "could not parse response: {}", e),
+            ProviderError::Decode(e) => write!(f, "could not parse streamed event: {}", e),
+            ProviderError::Empty => write!(f, "response contained no text"),
+            ProviderError::Unavailable => write!(f, "every provider's circuit breaker is open"),
+            ProviderError::BudgetExhausted => write!(f, "the daily budget for paid providers is spent"),
+            ProviderError::Blocked(reason) => write!(f, "{}", reason),
+            ProviderError::Recitation => write!(f, "stopped for recitation"),
+            ProviderError::Truncated => write!(f, "hit the output limit before producing any text"),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+/// A backend that can turn a prompt into a fortune.
+#[async_trait]
+pub trait FortuneProvider: Send + Sync {
+    /// Short identifier reported alongside generated fortunes.
+    fn name(&self) -> &'static str;
+
+    /// Whether calls cost money and so count against the daily budget.
+    fn metered(&self) -> bool {
+        false
+    }
+
+    /// Most fortunes one upstream call of `generate_batch` can produce.
+    fn fortunes_per_call(&self) -> usize {
+        1
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError>;
+
+    /// Streams the fortune as it is produced. Providers without native
+    /// streaming deliver the finished text as a single chunk.
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<StreamingGeneration, ProviderError> {
+        Ok(self.generate(prompt, options).await?.into())
+    }
+
+    /// Generates up to `count` fortunes in one go. Providers without a native
+    /// batch API run separate generations, a few at a time. Fortunes that
+    /// fail are left out, with the first failure kept in the batch.
+    async fn generate_batch(&self, prompt: &str, options: &GenerateOptions, count: usize) -> Batch {
+        let results: Vec<_> = stream::iter(0..count)
+            .map(|_| self.generate(prompt, options))
+            .buffer_unordered(BATCH_CONCURRENCY)
+            .collect()
+            .await;
+        let mut batch = Batch::default();
+        for result in results {
+            batch.calls += 1;
+            batch.keep(result);
+        }
+        batch
+    }
+}
+
+/// Builds a single provider by name. HTTP providers share `client`, and with
+/// it a connection pool.
+pub fn from_name(
+    name: &str,
+    config: &Config,
+    client: &reqwest::Client,
+) -> Result<Arc<dyn FortuneProvider>, ProviderError> {
+    match name {
+        "corpus" => Ok(Arc::new(CorpusProvider::bundled())),
+        "gemini" => Ok(Arc::new(GeminiProvider::from_config(config, client)?)),
+        "openai" => Ok(Arc::new(OpenAiProvider::from_config(config, client)?)),
+        "ollama" => Ok(Arc::new(OllamaProvider::from_config(config, client)?)),
+        "llamacpp" => Ok(Arc::new(LlamaCppProvider::from_config(config, client)?)),
+        other => Err(ProviderError::Unknown(other.to_string())),
+    }
+}
+
+/// Builds the failover chain listed in `FORTUNE_PROVIDER`. Providers missing
+/// their settings are left out with a warning, and the offline corpus is
+/// appended as the last resort unless `OFFLINE_FALLBACK` is off.
+pub fn chain_from_config(
+    config: &Config,
+    client: &reqwest::Client,
+) -> Result<ProviderChain, ProviderError> {
+    let mut providers: Vec<Arc<dyn FortuneProvider>> = Vec::new();
+    for name in &config.providers {
+        match from_name(name, config, client) {
+            Ok(provider) => providers.push(provider),
+            Err(ProviderError::NotConfigured(reason)) => {
+                eprintln!("⚠️  {} provider unavailable ({}); skipping it", name, reason);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let has_corpus = providers.iter().any(|p| p.name() == "corpus");
+    if !has_corpus && (config.offline_fallback || providers.is_empty()) {
+        providers.push(Arc::new(CorpusProvider::bundled()));
+    }
+
+    Ok(ProviderChain::new(
+        providers,
+        config.breaker_failure_threshold,
+        Duration::from_secs(config.breaker_cooldown_secs),
+        DailyBudget::new(config.budget_daily_requests, config.budget_daily_tokens),
+    ))
+}
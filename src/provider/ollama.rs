@@ -0,0 +1,130 @@
+use super::{FortuneProvider, GenerateOptions, Generation, ProviderError, RetryPolicy, Usage};
+use crate::config::Config;
+use crate::structured;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+struct GenerateRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    stream: bool,
+    /// A JSON schema constrains the output to match it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<Value>,
+    #[serde(skip_serializing_if = "ModelOptions::is_empty")]
+    options: ModelOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct ModelOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i32>,
+}
+
+impl ModelOptions {
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.num_predict.is_none()
+            && self.seed.is_none()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GenerateResponse {
+    model: Option<String>,
+    response: String,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+}
+
+/// A locally hosted Ollama server (`/api/generate`).
+pub struct OllamaProvider {
+    url: String,
+    model: String,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl OllamaProvider {
+    pub fn from_config(config: &Config, client: &reqwest::Client) -> Result<Self, ProviderError> {
+        Ok(OllamaProvider {
+            url: format!("{}/api/generate", config.ollama_base_url.trim_end_matches('/')),
+            model: config.ollama_model.clone(),
+            client: client.clone(),
+            retry: config.retry.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl FortuneProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError> {
+        let request_body = GenerateRequest {
+            model: &self.model,
+            prompt,
+            system: options.system.as_deref(),
+            stream: false,
+            format: options.structured.then(structured::json_schema),
+            options: ModelOptions {
+                temperature: options.temperature,
+                top_p: options.top_p,
+                num_predict: options.max_output_tokens,
+                seed: options.seed,
+            },
+        };
+
+        let response = self
+            .retry
+            .send(self.name(), || self.client.post(&self.url).json(&request_body))
+            .await?;
+
+        let data = response
+            .json::<GenerateResponse>()
+            .await
+            .map_err(ProviderError::body)?;
+
+        let truncated = data.done_reason.as_deref() == Some("length");
+        if data.response.trim().is_empty() {
+            return Err(if truncated {
+                ProviderError::Truncated
+            } else {
+                ProviderError::Empty
+            });
+        }
+
+        Ok(Generation {
+            text: data.response,
+            provider: self.name(),
+            model: data.model.unwrap_or_else(|| self.model.clone()),
+            truncated,
+            usage: match (data.prompt_eval_count, data.eval_count) {
+                (Some(prompt_tokens), Some(output_tokens)) => Some(Usage {
+                    prompt_tokens,
+                    output_tokens,
+                    total_tokens: prompt_tokens + output_tokens,
+                }),
+                _ => None,
+            },
+        })
+    }
+}
@@ -0,0 +1,164 @@
+use super::{FortuneProvider, GenerateOptions, Generation, ProviderError, RetryPolicy, Usage};
+use crate::config::Config;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    model: Option<String>,
+    choices: Vec<Choice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChoiceMessage {
+    content: Option<String>,
+}
+
+/// Any server speaking the OpenAI `/v1/chat/completions` protocol.
+pub struct OpenAiProvider {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl OpenAiProvider {
+    pub fn from_config(config: &Config, client: &reqwest::Client) -> Result<Self, ProviderError> {
+        Ok(OpenAiProvider {
+            url: format!("{}/chat/completions", config.openai_base_url.trim_end_matches('/')),
+            model: config.openai_model.clone(),
+            api_key: config.openai_api_key.clone(),
+            client: client.clone(),
+            retry: config.retry.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl FortuneProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn metered(&self) -> bool {
+        true
+    }
+
+    async fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerateOptions,
+    ) -> Result<Generation, ProviderError> {
+        let system = options.system.as_deref().map(|system| ChatMessage {
+            role: "system",
+            content: system,
+        });
+        let request_body = ChatRequest {
+            model: &self.model,
+            messages: system
+                .into_iter()
+                .chain([ChatMessage {
+                    role: "user",
+                    content: prompt,
+                }])
+                .collect(),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_output_tokens,
+            seed: options.seed,
+            response_format: options
+                .structured
+                .then_some(ResponseFormat { kind: "json_object" }),
+        };
+
+        let response = self
+            .retry
+            .send(self.name(), || {
+                let request = self.client.post(&self.url).json(&request_body);
+                match &self.api_key {
+                    Some(api_key) => request.bearer_auth(api_key),
+                    None => request,
+                }
+            })
+            .await?;
+
+        let data = response
+            .json::<ChatResponse>()
+            .await
+            .map_err(ProviderError::body)?;
+
+        let choice = data.choices.into_iter().next().ok_or(ProviderError::Empty)?;
+        match choice.finish_reason.as_deref() {
+            Some("content_filter") => {
+                return Err(ProviderError::Blocked(
+                    "response blocked by the content filter".to_string(),
+                ))
+            }
+            Some("length") if choice.message.content.as_deref().is_none_or(|t| t.trim().is_empty()) => {
+                return Err(ProviderError::Truncated)
+            }
+            _ => {}
+        }
+        let truncated = choice.finish_reason.as_deref() == Some("length");
+        let text = choice
+            .message
+            .content
+            .filter(|text| !text.trim().is_empty())
+            .ok_or(ProviderError::Empty)?;
+
+        Ok(Generation {
+            text,
+            provider: self.name(),
+            model: data.model.unwrap_or_else(|| self.model.clone()),
+            truncated,
+            usage: data.usage.map(|usage| Usage {
+                prompt_tokens: usage.prompt_tokens,
+                output_tokens: usage.completion_tokens,
+                total_tokens: usage.total_tokens,
+            }),
+        })
+    }
+}
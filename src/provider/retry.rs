@@ -0,0 +1,133 @@
+//! Status-aware retries for provider HTTP calls: rate limits and server
+//! errors are retried with jittered exponential backoff, everything else
+//! fails straight away.
+
+use super::ProviderError;
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Longest error body kept in `ProviderError::Status`.
+const MAX_ERROR_MESSAGE_CHARS: usize = 200;
+
+/// How persistently a provider call is retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first.
+    pub max_attempts: u32,
+    /// Backoff ceiling before the first retry; doubles with each retry.
+    pub base_delay: Duration,
+    /// Cap on any single backoff.
+    pub max_delay: Duration,
+    /// Budget for all attempts and the waits between them.
+    pub deadline: Duration,
+}
+
+impl RetryPolicy {
+    /// Sends the request produced by `build`, retrying `429`, `5xx` and
+    /// connection failures. Any other `4xx`, such as a rejected API key, is
+    /// returned at once. A retry is only attempted if its wait fits in what
+    /// is left of the deadline.
+    pub async fn send<F>(&self, provider: &str, build: F) -> Result<Response, ProviderError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let (error, retry_after) = match build().send().await {
+                Ok(response) if response.status().is_success() => {
+                    if attempt > 1 {
+                        eprintln!("🔁 {} succeeded on attempt {}", provider, attempt);
+                    }
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = retry_after(&response);
+                    let message = error_message(&response.text().await.unwrap_or_default());
+                    let error = ProviderError::Status(status, message);
+                    if !is_retryable(status) {
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => (ProviderError::Request(e), None),
+                Err(e) => return Err(ProviderError::Request(e)),
+            };
+
+            if attempt >= self.max_attempts {
+                eprintln!("🔁 {} gave up after {} attempts", provider, attempt);
+                return Err(error);
+            }
+            let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+            if started.elapsed() + delay > self.deadline {
+                eprintln!(
+                    "🔁 {} attempt {}/{} failed ({}); waiting {}ms would pass the deadline, giving up",
+                    provider,
+                    attempt,
+                    self.max_attempts,
+                    error,
+                    delay.as_millis()
+                );
+                return Err(error);
+            }
+            eprintln!(
+                "🔁 {} attempt {}/{} failed ({}); retrying in {}ms",
+                provider,
+                attempt,
+                self.max_attempts,
+                error,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// "Full jitter" backoff: a random wait up to `base_delay * 2^(attempt-1)`,
+    /// capped at `max_delay`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        let millis = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::rng().random_range(0..=millis))
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads `Retry-After`, given either as seconds or as an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Pulls the human-readable part out of an error body. Gemini and OpenAI
+/// send `{"error": {"message": ...}}`, Ollama sends `{"error": "..."}`.
+fn error_message(body: &str) -> String {
+    let message = match serde_json::from_str::<Value>(body) {
+        Ok(json) => match &json["error"] {
+            Value::String(message) => message.clone(),
+            error => error["message"].as_str().unwrap_or(body).to_string(),
+        },
+        Err(_) => body.to_string(),
+    };
+    let message = message.trim();
+    match message.char_indices().nth(MAX_ERROR_MESSAGE_CHARS) {
+        Some((end, _)) => format!("{}…", &message[..end]),
+        None => message.to_string(),
+    }
+}
@@ -0,0 +1,72 @@
+//! Minimal reader for upstream Server-Sent Events responses.
+
+use super::ProviderError;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Yields the payload of every `data:` line in an SSE response body. Fails
+/// with `Timeout` if the upstream sends nothing for `read_timeout`.
+pub fn data_events(
+    response: reqwest::Response,
+    read_timeout: Duration,
+) -> BoxStream<'static, Result<String, ProviderError>> {
+    struct Reader {
+        bytes: BoxStream<'static, reqwest::Result<bytes::Bytes>>,
+        buffer: Vec<u8>,
+        ready: VecDeque<String>,
+        finished: bool,
+    }
+
+    let reader = Reader {
+        bytes: response.bytes_stream().boxed(),
+        buffer: Vec::new(),
+        ready: VecDeque::new(),
+        finished: false,
+    };
+
+    stream::unfold(reader, move |mut reader| async move {
+        loop {
+            if let Some(data) = reader.ready.pop_front() {
+                return Some((Ok(data), reader));
+            }
+            if reader.finished {
+                return None;
+            }
+            let Ok(next) = tokio::time::timeout(read_timeout, reader.bytes.next()).await else {
+                reader.finished = true;
+                return Some((Err(ProviderError::Timeout), reader));
+            };
+            match next {
+                Some(Ok(chunk)) => {
+                    reader.buffer.extend_from_slice(&chunk);
+                    while let Some(end) = reader.buffer.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = reader.buffer.drain(..=end).collect();
+                        push_data(&mut reader.ready, &line);
+                    }
+                }
+                Some(Err(e)) => {
+                    reader.finished = true;
+                    let error = if e.is_timeout() { ProviderError::Timeout } else { ProviderError::Request(e) };
+                    return Some((Err(error), reader));
+                }
+                None => {
+                    let rest = std::mem::take(&mut reader.buffer);
+                    push_data(&mut reader.ready, &rest);
+                    reader.finished = true;
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+fn push_data(ready: &mut VecDeque<String>, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    if let Some(data) = line.trim_end().strip_prefix("data:") {
+        let data = data.trim_start();
+        if !data.is_empty() {
+            ready.push_back(data.to_string());
+        }
+    }
+}
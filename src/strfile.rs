@@ -0,0 +1,308 @@
+//! Reader for classic BSD `fortune(6)` databases: `%`-separated text files,
+//! optionally accompanied by a `.dat` index produced by `strfile(8)`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Size of the fixed `strfile` header: five big-endian `u32`s, the delimiter
+/// byte and three bytes of padding.
+const HEADER_LEN: usize = 24;
+/// `str_flags` bit marking a database whose entries are rot13-encoded.
+const STR_ROTATED: u32 = 0x4;
+
+/// Extensions that sit next to fortune files but aren't fortune files.
+const SKIPPED_EXTENSIONS: &[&str] = &["dat", "u8", "pdat"];
+
+/// One entry drawn from a fortune database.
+#[derive(Debug, Clone)]
+pub struct FileFortune {
+    pub text: String,
+    /// Name of the database file the entry came from.
+    pub database: String,
+}
+
+/// Parsed contents of a `.dat` index.
+#[derive(Debug)]
+struct StrfileIndex {
+    delim: u8,
+    rotated: bool,
+    offsets: Vec<u64>,
+}
+
+/// Loads every fortune database in `dir`. Files that can't be read or decoded
+/// are reported and skipped; only an unreadable directory is an error.
+pub fn load_dir(dir: &Path) -> io::Result<Vec<FileFortune>> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && is_database(path))
+        .collect();
+    paths.sort();
+
+    let mut fortunes = Vec::new();
+    for path in paths {
+        match load_file(&path) {
+            Ok(entries) => fortunes.extend(entries),
+            Err(e) => eprintln!("Skipping fortune file {}: {}", path.display(), e),
+        }
+    }
+    Ok(fortunes)
+}
+
+fn is_database(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_none_or(|name| name.starts_with('.'));
+    let skipped = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SKIPPED_EXTENSIONS.contains(&ext));
+    !hidden && !skipped
+}
+
+/// Reads one database, using its `.dat` index when present and valid.
+fn load_file(path: &Path) -> io::Result<Vec<FileFortune>> {
+    let text = fs::read(path)?;
+    let database = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut dat_path = path.as_os_str().to_owned();
+    dat_path.push(".dat");
+    let index = match fs::read(&dat_path) {
+        Ok(bytes) => match parse_index(&bytes, text.len()) {
+            Ok(index) => Some(index),
+            Err(reason) => {
+                eprintln!("Ignoring strfile index for {}: {}", path.display(), reason);
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
+    let entries = match index {
+        Some(index) => split_indexed(&text, &index),
+        None => split_plain(&text, b'%'),
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|text| FileFortune {
+            text,
+            database: database.clone(),
+        })
+        .collect())
+}
+
+/// Decodes a `.dat` header and offset table. Offsets are 32-bit in
+/// fortune-mod and 64-bit in newer BSDs, so the width is inferred from the
+/// table size.
+fn parse_index(bytes: &[u8], text_len: usize) -> Result<StrfileIndex, String> {
+    if bytes.len() < HEADER_LEN {
+        return Err("file is shorter than the strfile header".to_string());
+    }
+    let word = |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    let version = word(0);
+    let numstr = word(4) as usize;
+    let flags = word(16);
+    let delim = bytes[20];
+
+    if version == 0 || version > 2 {
+        return Err(format!("unsupported strfile version {}", version));
+    }
+
+    let table = &bytes[HEADER_LEN..];
+    let slots = numstr + 1;
+    let offsets: Vec<u64> = if table.len() == slots * 4 {
+        table
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]) as u64)
+            .collect()
+    } else if table.len() == slots * 8 {
+        table
+            .chunks_exact(8)
+            .map(|c| u64::from_be_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]))
+            .collect()
+    } else {
+        return Err(format!(
+            "offset table is {} bytes, expected {} entries",
+            table.len(),
+            slots
+        ));
+    };
+
+    if offsets.windows(2).any(|w| w[0] > w[1]) || offsets.last().is_some_and(|&end| end > text_len as u64) {
+        return Err("offsets don't match the text file; re-run strfile".to_string());
+    }
+
+    Ok(StrfileIndex {
+        delim,
+        rotated: flags & STR_ROTATED != 0,
+        offsets,
+    })
+}
+
+fn split_indexed(text: &[u8], index: &StrfileIndex) -> Vec<String> {
+    index
+        .offsets
+        .windows(2)
+        .filter_map(|w| {
+            let raw = &text[w[0] as usize..w[1] as usize];
+            let entry = strip_delimiter(&String::from_utf8_lossy(raw), index.delim);
+            let entry = if index.rotated { rot13(&entry) } else { entry };
+            (!entry.is_empty()).then_some(entry)
+        })
+        .collect()
+}
+
+/// Splits a plain fortune file on lines consisting solely of `delim`.
+fn split_plain(text: &[u8], delim: u8) -> Vec<String> {
+    let text = String::from_utf8_lossy(text);
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if is_delimiter(line, delim) {
+            push_entry(&mut entries, &current);
+            current.clear();
+        } else {
+            current.push_str(line);
+            current.push('\n');
+        }
+    }
+    push_entry(&mut entries, &current);
+    entries
+}
+
+fn push_entry(entries: &mut Vec<String>, raw: &str) {
+    let entry = raw.trim_end();
+    if !entry.trim().is_empty() {
+        entries.push(entry.to_string());
+    }
+}
+
+/// Drops the trailing delimiter line that an indexed slice still contains.
+fn strip_delimiter(raw: &str, delim: u8) -> String {
+    let lines: Vec<&str> = raw.lines().collect();
+    let end = lines
+        .iter()
+        .rposition(|line| !is_delimiter(line, delim) && !line.trim().is_empty())
+        .map_or(0, |i| i + 1);
+    let start = lines
+        .iter()
+        .position(|line| !is_delimiter(line, delim))
+        .unwrap_or(end)
+        .min(end);
+    lines[start..end].join("\n")
+}
+
+fn is_delimiter(line: &str, delim: u8) -> bool {
+    line.trim_end_matches('\r').as_bytes() == [delim]
+}
+
+fn rot13(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='z' => (((c as u8 - b'a') + 13) % 26 + b'a') as char,
+            'A'..='Z' => (((c as u8 - b'A') + 13) % 26 + b'A') as char,
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"Hug a tree.\n%\nBe the tree.\n%\n";
+    const OFFSETS: &[u64] = &[0, 14, 29];
+
+    /// A `.dat` file for `offsets`, written `width` bytes per offset.
+    fn dat(flags: u32, delim: u8, offsets: &[u64], width: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for word in [2, offsets.len() as u32 - 1, 12, 11, flags] {
+            bytes.extend(word.to_be_bytes());
+        }
+        bytes.extend([delim, 0, 0, 0]);
+        for &offset in offsets {
+            match width {
+                4 => bytes.extend((offset as u32).to_be_bytes()),
+                _ => bytes.extend(offset.to_be_bytes()),
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_32_and_64_bit_offsets() {
+        for width in [4, 8] {
+            let index = parse_index(&dat(0, b'%', OFFSETS, width), TEXT.len()).unwrap();
+            assert_eq!(index.offsets, OFFSETS);
+            assert_eq!(index.delim, b'%');
+            assert!(!index.rotated);
+            assert_eq!(split_indexed(TEXT, &index), ["Hug a tree.", "Be the tree."]);
+        }
+    }
+
+    #[test]
+    fn decodes_rotated_entries() {
+        let text = b"Uht n gerr.\n%\nOr gur gerr.\n%\n";
+        let index = parse_index(&dat(STR_ROTATED, b'%', OFFSETS, 4), text.len()).unwrap();
+        assert!(index.rotated);
+        assert_eq!(split_indexed(text, &index), ["Hug a tree.", "Be the tree."]);
+    }
+
+    #[test]
+    fn honours_a_custom_delimiter() {
+        let text = b"Nap.\n#\nNap again.\n#\n";
+        let index = parse_index(&dat(0, b'#', &[0, 7, 20], 4), text.len()).unwrap();
+        assert_eq!(split_indexed(text, &index), ["Nap.", "Nap again."]);
+    }
+
+    #[test]
+    fn rejects_bad_indexes() {
+        assert!(parse_index(&[0; 10], TEXT.len()).is_err());
+        let mut unversioned = dat(0, b'%', OFFSETS, 4);
+        unversioned[..4].copy_from_slice(&7u32.to_be_bytes());
+        assert!(parse_index(&unversioned, TEXT.len()).is_err());
+        // The table doesn't fit the entry count.
+        let mut short = dat(0, b'%', OFFSETS, 4);
+        short.truncate(short.len() - 2);
+        assert!(parse_index(&short, TEXT.len()).is_err());
+        // Out of order, or past the end of the text.
+        assert!(parse_index(&dat(0, b'%', &[0, 20, 14], 4), TEXT.len()).is_err());
+        assert!(parse_index(&dat(0, b'%', &[0, 14, 40], 4), TEXT.len()).is_err());
+    }
+
+    #[test]
+    fn splits_plain_files() {
+        let text = b"Hug a tree.\r\n%\r\n\n%\nBe the tree.\n  and then some\n%";
+        assert_eq!(
+            split_plain(text, b'%'),
+            ["Hug a tree.", "Be the tree.\n  and then some"]
+        );
+        assert_eq!(
+            split_plain(b"No delimiters at all.\n", b'%'),
+            ["No delimiters at all."]
+        );
+        assert!(split_plain(b"%\n%\n", b'%').is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_plain_splitting_on_a_bad_index() {
+        let dir = std::env::temp_dir().join(format!("strfile-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("trees"), TEXT).unwrap();
+        fs::write(dir.join("trees.dat"), dat(0, b'%', &[0, 14, 99], 4)).unwrap();
+
+        let fortunes = load_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let texts: Vec<_> = fortunes
+            .iter()
+            .map(|fortune| fortune.text.as_str())
+            .collect();
+        assert_eq!(texts, ["Hug a tree.", "Be the tree."]);
+        assert!(fortunes.iter().all(|fortune| fortune.database == "trees"));
+    }
+}
@@ -0,0 +1,380 @@
+//! Structured fortunes: the model is asked for JSON with the advice and its
+//! trimmings as separate fields. Whatever comes back is validated field by
+//! field, and plain prose is accepted as the advice with derived trimmings.
+
+use crate::daily;
+use crate::provider::ProviderError;
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Appended to the prompt so providers without schema support know the shape.
+pub const PROMPT_INSTRUCTIONS: &str = "\nRespond only with a JSON object with these fields: \"advice\" (the advice itself), \"lucky_numbers\" (three to six integers from 1 to 99), \"mood\" (one lowercase word), \"emoji\" (a single emoji) and \"how_to_apply\" (one short sentence on putting the advice into practice).";
+
+/// Field order matters when streaming: `advice` comes first so it can be
+/// revealed before the rest of the object arrives.
+pub const FIELD_ORDER: &[&str] = &["advice", "lucky_numbers", "mood", "emoji", "how_to_apply"];
+
+const DEFAULT_MOOD: &str = "mysterious";
+const DEFAULT_EMOJI: &str = "🥠";
+const DEFAULT_HOW_TO_APPLY: &str = "Begin at once, and tell no one why.";
+const MAX_HOW_TO_APPLY_CHARS: usize = 200;
+
+/// JSON Schema for a structured fortune.
+pub fn json_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "advice": { "type": "string" },
+            "lucky_numbers": {
+                "type": "array",
+                "items": { "type": "integer", "minimum": 1, "maximum": 99 },
+                "minItems": 3,
+                "maxItems": 6
+            },
+            "mood": { "type": "string" },
+            "emoji": { "type": "string" },
+            "how_to_apply": { "type": "string" }
+        },
+        "required": FIELD_ORDER
+    })
+}
+
+/// Everything in a structured fortune besides the advice itself.
+#[derive(Debug, Clone, Serialize)]
+pub struct FortuneExtras {
+    pub lucky_numbers: Vec<u8>,
+    pub mood: String,
+    pub emoji: String,
+    pub how_to_apply: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StructuredFortune {
+    pub advice: String,
+    #[serde(flatten)]
+    pub extras: FortuneExtras,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawFortune {
+    #[serde(default)]
+    advice: String,
+    #[serde(default)]
+    lucky_numbers: Vec<Value>,
+    #[serde(default)]
+    mood: String,
+    #[serde(default)]
+    emoji: String,
+    #[serde(default)]
+    how_to_apply: String,
+}
+
+/// Parses model output into a structured fortune. Invalid fields are replaced
+/// with defaults; prose that isn't JSON at all becomes the advice. Only JSON
+/// with no recoverable advice is an error.
+pub fn parse(text: &str) -> Result<StructuredFortune, ProviderError> {
+    let body = strip_code_fence(text);
+    if !body.starts_with('{') {
+        return Ok(from_plain(text.trim()));
+    }
+
+    match serde_json::from_str::<RawFortune>(body) {
+        Ok(raw) if !raw.advice.trim().is_empty() => Ok(validate(raw)),
+        Ok(_) => Err(ProviderError::Empty),
+        Err(e) => match partial_advice(body).filter(|advice| !advice.trim().is_empty()) {
+            Some(advice) => {
+                eprintln!("⚠️  Structured fortune was malformed ({}); keeping only the advice", e);
+                Ok(from_plain(advice.trim()))
+            }
+            None => Err(ProviderError::Decode(e)),
+        },
+    }
+}
+
+/// Wraps plain advice with trimmings derived from its text.
+pub fn from_plain(advice: &str) -> StructuredFortune {
+    StructuredFortune {
+        advice: advice.to_string(),
+        extras: FortuneExtras {
+            lucky_numbers: derived_lucky_numbers(advice),
+            mood: DEFAULT_MOOD.to_string(),
+            emoji: DEFAULT_EMOJI.to_string(),
+            how_to_apply: DEFAULT_HOW_TO_APPLY.to_string(),
+        },
+    }
+}
+
+fn validate(raw: RawFortune) -> StructuredFortune {
+    let advice = raw.advice.trim().to_string();
+    let mut repaired = Vec::new();
+
+    let mut lucky_numbers: Vec<u8> = Vec::new();
+    for number in raw.lucky_numbers.iter().filter_map(Value::as_u64) {
+        if (1..=99).contains(&number) && !lucky_numbers.contains(&(number as u8)) {
+            lucky_numbers.push(number as u8);
+        }
+    }
+    lucky_numbers.truncate(6);
+    if lucky_numbers.len() < 3 {
+        repaired.push("lucky_numbers");
+        lucky_numbers = derived_lucky_numbers(&advice);
+    }
+
+    let mood = raw.mood.trim().to_lowercase();
+    let mood = if !mood.is_empty() && mood.len() <= 24 && mood.chars().all(|c| c.is_alphabetic() || c == '-') {
+        mood
+    } else {
+        repaired.push("mood");
+        DEFAULT_MOOD.to_string()
+    };
+
+    let emoji = raw.emoji.trim();
+    let emoji = if !emoji.is_empty() && emoji.chars().count() <= 8 && !emoji.chars().any(char::is_alphanumeric) {
+        emoji.to_string()
+    } else {
+        repaired.push("emoji");
+        DEFAULT_EMOJI.to_string()
+    };
+
+    let how_to_apply = raw.how_to_apply.trim();
+    let how_to_apply = if !how_to_apply.is_empty() && how_to_apply.chars().count() <= MAX_HOW_TO_APPLY_CHARS {
+        how_to_apply.to_string()
+    } else {
+        repaired.push("how_to_apply");
+        DEFAULT_HOW_TO_APPLY.to_string()
+    };
+
+    if !repaired.is_empty() {
+        eprintln!("⚠️  Structured fortune had invalid {}; used defaults", repaired.join(", "));
+    }
+
+    StructuredFortune {
+        advice,
+        extras: FortuneExtras {
+            lucky_numbers,
+            mood,
+            emoji,
+            how_to_apply,
+        },
+    }
+}
+
+/// Four distinct numbers seeded by the advice, so the same advice always
+/// brings the same luck.
+fn derived_lucky_numbers(advice: &str) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(daily::fnv1a(advice.as_bytes()));
+    let mut numbers: Vec<u8> = index::sample(&mut rng, 99, 4)
+        .into_iter()
+        .map(|i| i as u8 + 1)
+        .collect();
+    numbers.sort_unstable();
+    numbers
+}
+
+/// Removes a surrounding Markdown code fence such as ```` ```json ````.
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(rest) = text.strip_prefix("```") else {
+        return text;
+    };
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_alphanumeric());
+    rest.strip_suffix("```").unwrap_or(rest).trim()
+}
+
+/// Decodes as much of the `advice` string as has arrived, stopping before a
+/// half-received escape. Returns `None` until the value has started.
+fn partial_advice(json: &str) -> Option<String> {
+    let key = json.find("\"advice\"")?;
+    let rest = json[key + "\"advice\"".len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+
+    let mut advice = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        match u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4) {
+                            Some(code) => char::from_u32(code).unwrap_or('\u{fffd}'),
+                            None => break,
+                        }
+                    }
+                    Some(other) => other,
+                    None => break,
+                };
+                advice.push(escaped);
+            }
+            c => advice.push(c),
+        }
+    }
+    Some(advice)
+}
+
+/// Incrementally extracts the advice from streamed model output, so the cookie
+/// can reveal it while the rest of the JSON object is still arriving.
+#[derive(Default)]
+pub struct AdviceStream {
+    buffer: String,
+    /// Bytes of advice (or of plain text) already handed out.
+    emitted: usize,
+    /// `Some(true)` once the output looks like JSON, `Some(false)` for prose.
+    json: Option<bool>,
+}
+
+impl AdviceStream {
+    /// Adds a chunk of model output and returns the newly revealed advice.
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.buffer.push_str(chunk);
+        if self.json.is_none() {
+            let Some(first) = self.buffer.trim_start().chars().next() else {
+                return String::new();
+            };
+            self.json = Some(first == '{' || first == '`');
+        }
+
+        let revealed = if self.json == Some(true) {
+            partial_advice(&self.buffer).unwrap_or_default()
+        } else {
+            self.buffer.clone()
+        };
+        let delta = revealed.get(self.emitted..).unwrap_or_default().to_string();
+        self.emitted = revealed.len().max(self.emitted);
+        delta
+    }
+
+    /// Parses everything received once the stream has ended.
+    pub fn finish(self) -> Result<StructuredFortune, ProviderError> {
+        parse(&self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_valid_json() {
+        let text = r#"{"advice": " Hug a cactus. ", "lucky_numbers": [7, 13, 42], "mood": "Prickly", "emoji": "🌵", "how_to_apply": "Gently."}"#;
+        let fortune = parse(text).unwrap();
+        assert_eq!(fortune.advice, "Hug a cactus.");
+        assert_eq!(fortune.extras.lucky_numbers, [7, 13, 42]);
+        assert_eq!(fortune.extras.mood, "prickly");
+        assert_eq!(fortune.extras.emoji, "🌵");
+        assert_eq!(fortune.extras.how_to_apply, "Gently.");
+    }
+
+    #[test]
+    fn strips_code_fences() {
+        let text = "```json\n{\"advice\": \"Nap.\", \"lucky_numbers\": [1, 2, 3]}\n```";
+        assert_eq!(parse(text).unwrap().advice, "Nap.");
+        assert_eq!(strip_code_fence("```\nplain\n```"), "plain");
+        assert_eq!(strip_code_fence("  no fence "), "no fence");
+    }
+
+    #[test]
+    fn repairs_invalid_fields() {
+        let text = r#"{"advice": "Nap.", "lucky_numbers": [0, 5, 5, 100, "7", 8], "mood": "very smug", "emoji": "ok", "how_to_apply": ""}"#;
+        let fortune = parse(text).unwrap();
+        // Too few valid, distinct numbers survive, so they are derived instead.
+        assert_eq!(fortune.extras.lucky_numbers, derived_lucky_numbers("Nap."));
+        assert_eq!(fortune.extras.mood, DEFAULT_MOOD);
+        assert_eq!(fortune.extras.emoji, DEFAULT_EMOJI);
+        assert_eq!(fortune.extras.how_to_apply, DEFAULT_HOW_TO_APPLY);
+
+        let text = r#"{"advice": "Nap.", "lucky_numbers": [1, 2, 3, 4, 5, 6, 7, 8]}"#;
+        assert_eq!(
+            parse(text).unwrap().extras.lucky_numbers,
+            [1, 2, 3, 4, 5, 6]
+        );
+    }
+
+    #[test]
+    fn accepts_prose_as_advice() {
+        let fortune = parse("  Befriend a lamp.\n").unwrap();
+        assert_eq!(fortune.advice, "Befriend a lamp.");
+        assert_eq!(fortune.extras.mood, DEFAULT_MOOD);
+    }
+
+    #[test]
+    fn derives_the_same_luck_for_the_same_advice() {
+        let numbers = derived_lucky_numbers("Befriend a lamp.");
+        assert_eq!(numbers, derived_lucky_numbers("Befriend a lamp."));
+        assert_eq!(numbers.len(), 4);
+        assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(numbers.iter().all(|number| (1..=99).contains(number)));
+    }
+
+    #[test]
+    fn salvages_advice_from_malformed_json() {
+        let fortune = parse(r#"{"advice": "Truncated advi"#).unwrap();
+        assert_eq!(fortune.advice, "Truncated advi");
+        assert!(matches!(
+            parse(r#"{"advice": "", "mood": "sad"}"#),
+            Err(ProviderError::Empty)
+        ));
+        assert!(matches!(
+            parse(r#"{"mood": "sad""#),
+            Err(ProviderError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn decodes_partial_advice() {
+        assert_eq!(partial_advice(r#"{"mood": "x""#), None);
+        assert_eq!(partial_advice(r#"{"advice""#), None);
+        assert_eq!(partial_advice(r#"{"advice": ""#).as_deref(), Some(""));
+        assert_eq!(
+            partial_advice(r#"{"advice" : "Say \"hi\"\n to"#).as_deref(),
+            Some("Say \"hi\"\n to")
+        );
+        assert_eq!(
+            partial_advice(r#"{"advice": "café done", "mood""#).as_deref(),
+            Some("café done")
+        );
+        // A half-received escape is held back.
+        assert_eq!(
+            partial_advice(r#"{"advice": "caf\u00"#).as_deref(),
+            Some("caf")
+        );
+        assert_eq!(
+            partial_advice(r#"{"advice": "line\"#).as_deref(),
+            Some("line")
+        );
+    }
+
+    #[test]
+    fn streams_advice_as_it_arrives() {
+        let mut stream = AdviceStream::default();
+        let chunks = [
+            " ",
+            "{\"adv",
+            "ice\": \"Hug ",
+            "a \\u00",
+            "e9tre",
+            "e.\", \"lucky_numbers\": [1, 2, 3]}",
+        ];
+        let revealed: Vec<String> = chunks.iter().map(|chunk| stream.push(chunk)).collect();
+        assert_eq!(revealed, ["", "", "Hug ", "a ", "étre", "e."]);
+        assert_eq!(stream.finish().unwrap().advice, "Hug a étree.");
+    }
+
+    #[test]
+    fn streams_prose_unchanged() {
+        let mut stream = AdviceStream::default();
+        assert_eq!(stream.push("Whisper to "), "Whisper to ");
+        assert_eq!(stream.push("your toaster."), "your toaster.");
+        assert_eq!(stream.finish().unwrap().advice, "Whisper to your toaster.");
+    }
+}
@@ -0,0 +1,355 @@
+//! Prompt templates: `PROMPT` may use `{{variable}}` placeholders and
+//! `{{#if variable}}...{{else}}...{{/if}}` blocks, optionally comparing with
+//! `{{#if variable == "value"}}`. Templates are parsed once at startup so a
+//! typo is reported before the first cookie is cracked.
+
+use chrono::{Local, NaiveDate, Timelike};
+use std::fmt;
+
+/// Everything a template can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Var {
+    Date,
+    Weekday,
+    Vibe,
+    Locale,
+    TimeOfDay,
+}
+
+const VARS: &[(&str, Var)] = &[
+    ("date", Var::Date),
+    ("weekday", Var::Weekday),
+    ("vibe", Var::Vibe),
+    ("locale", Var::Locale),
+    ("time_of_day", Var::TimeOfDay),
+];
+
+impl Var {
+    fn parse(name: &str) -> Option<Var> {
+        VARS.iter().find(|(known, _)| *known == name).map(|(_, var)| *var)
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var(Var),
+    If {
+        var: Var,
+        /// Compare with this value; without it, any non-empty value passes.
+        equals: Option<String>,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+/// Where a template went wrong, as a 1-based line and column.
+#[derive(Debug)]
+pub struct TemplateError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// The values substituted into a template for one prompt.
+#[derive(Debug, Clone)]
+pub struct PromptVars<'a> {
+    pub date: NaiveDate,
+    /// Hour of the day, 0 to 23.
+    pub hour: u32,
+    pub vibe: Option<&'a str>,
+    pub locale: &'a str,
+}
+
+impl<'a> PromptVars<'a> {
+    /// Values for a prompt generated right now.
+    pub fn now(vibe: Option<&'a str>, locale: &'a str) -> Self {
+        let now = Local::now();
+        PromptVars {
+            date: now.date_naive(),
+            hour: now.hour(),
+            vibe: vibe.map(str::trim).filter(|vibe| !vibe.is_empty()),
+            locale,
+        }
+    }
+
+    fn value(&self, var: Var) -> String {
+        match var {
+            Var::Date => self.date.format("%B %-d, %Y").to_string(),
+            Var::Weekday => self.date.format("%A").to_string(),
+            Var::Vibe => self.vibe.unwrap_or_default().to_string(),
+            Var::Locale => self.locale.to_string(),
+            Var::TimeOfDay => match self.hour {
+                5..=11 => "morning",
+                12..=16 => "afternoon",
+                17..=20 => "evening",
+                _ => "night",
+            }
+            .to_string(),
+        }
+    }
+}
+
+/// What a `{{...}}` tag asks for.
+enum Tag<'a> {
+    Var(&'a str),
+    If(&'a str),
+    Else,
+    EndIf,
+}
+
+/// The block being filled while parsing: its nodes so far, and for an open
+/// `{{#if}}` what it tests and where it was opened.
+struct Block {
+    nodes: Vec<Node>,
+    condition: Option<(Var, Option<String>, usize)>,
+    then: Option<Vec<Node>>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, TemplateError> {
+        let error = |offset: usize, message: String| {
+            let before = &source[..offset];
+            TemplateError {
+                line: before.matches('\n').count() + 1,
+                column: before.rsplit('\n').next().unwrap_or_default().chars().count() + 1,
+                message,
+            }
+        };
+
+        let mut stack = vec![Block { nodes: Vec::new(), condition: None, then: None }];
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            let offset = source.len() - rest.len() + start;
+            if start > 0 {
+                stack.last_mut().unwrap().nodes.push(Node::Text(rest[..start].to_string()));
+            }
+            let Some(end) = rest[start..].find("}}") else {
+                return Err(error(offset, "\"{{\" is never closed with \"}}\"".to_string()));
+            };
+            let inner = rest[start + 2..start + end].trim();
+            rest = &rest[start + end + 2..];
+
+            let tag = match inner.split_once(char::is_whitespace) {
+                Some(("#if", condition)) => Tag::If(condition.trim()),
+                _ if inner == "else" => Tag::Else,
+                _ if inner == "/if" => Tag::EndIf,
+                _ if inner == "#if" => {
+                    return Err(error(offset, "{{#if}} needs a variable to test, as in {{#if vibe}}".to_string()));
+                }
+                _ if inner.starts_with(['#', '/']) => {
+                    return Err(error(offset, format!("unknown tag {{{{{}}}}}; only #if, else and /if are supported", inner)));
+                }
+                _ => Tag::Var(inner),
+            };
+            let variable = |name: &str| {
+                Var::parse(name).ok_or_else(|| {
+                    let known: Vec<&str> = VARS.iter().map(|(name, _)| *name).collect();
+                    error(offset, format!("unknown variable \"{}\"; expected one of {}", name, known.join(", ")))
+                })
+            };
+
+            match tag {
+                Tag::Var(name) => {
+                    let var = variable(name)?;
+                    stack.last_mut().unwrap().nodes.push(Node::Var(var));
+                }
+                Tag::If(condition) => {
+                    let (name, equals) = match condition.split_once("==") {
+                        Some((name, value)) => {
+                            let value = value.trim();
+                            let Some(value) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) else {
+                                return Err(error(offset, format!("compare with a quoted value, as in {{{{#if {} == \"...\"}}}}", name.trim())));
+                            };
+                            (name.trim(), Some(value.to_string()))
+                        }
+                        None => (condition, None),
+                    };
+                    let var = variable(name)?;
+                    stack.push(Block { nodes: Vec::new(), condition: Some((var, equals, offset)), then: None });
+                }
+                Tag::Else => {
+                    let block = stack.last_mut().unwrap();
+                    if block.condition.is_none() || block.then.is_some() {
+                        return Err(error(offset, "{{else}} without a matching {{#if}}".to_string()));
+                    }
+                    block.then = Some(std::mem::take(&mut block.nodes));
+                }
+                Tag::EndIf => {
+                    if stack.len() == 1 {
+                        return Err(error(offset, "{{/if}} without a matching {{#if}}".to_string()));
+                    }
+                    let block = stack.pop().unwrap();
+                    let (var, equals, _) = block.condition.unwrap();
+                    let node = match block.then {
+                        Some(then) => Node::If { var, equals, then, otherwise: block.nodes },
+                        None => Node::If { var, equals, then: block.nodes, otherwise: Vec::new() },
+                    };
+                    stack.last_mut().unwrap().nodes.push(node);
+                }
+            }
+        }
+
+        if let Some((_, _, opened)) = stack.last().unwrap().condition {
+            return Err(error(opened, "{{#if}} is never closed with {{/if}}".to_string()));
+        }
+        let mut root = stack.pop().unwrap();
+        if !rest.is_empty() {
+            root.nodes.push(Node::Text(rest.to_string()));
+        }
+        Ok(Template { nodes: root.nodes })
+    }
+
+    /// Whether the template mentions `var` anywhere, conditions included.
+    pub fn uses(&self, var: Var) -> bool {
+        fn mentions(nodes: &[Node], var: Var) -> bool {
+            nodes.iter().any(|node| match node {
+                Node::Text(_) => false,
+                Node::Var(used) => *used == var,
+                Node::If { var: used, then, otherwise, .. } => {
+                    *used == var || mentions(then, var) || mentions(otherwise, var)
+                }
+            })
+        }
+        mentions(&self.nodes, var)
+    }
+
+    pub fn render(&self, vars: &PromptVars) -> String {
+        fn render_into(out: &mut String, nodes: &[Node], vars: &PromptVars) {
+            for node in nodes {
+                match node {
+                    Node::Text(text) => out.push_str(text),
+                    Node::Var(var) => out.push_str(&vars.value(*var)),
+                    Node::If { var, equals, then, otherwise } => {
+                        let value = vars.value(*var);
+                        let holds = match equals {
+                            Some(expected) => value.eq_ignore_ascii_case(expected),
+                            None => !value.is_empty(),
+                        };
+                        render_into(out, if holds { then } else { otherwise }, vars);
+                    }
+                }
+            }
+        }
+        let mut out = String::new();
+        render_into(&mut out, &self.nodes, vars);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vibe: Option<&str>, hour: u32) -> PromptVars<'_> {
+        PromptVars {
+            date: NaiveDate::from_ymd_opt(2025, 1, 31).unwrap(),
+            hour,
+            vibe,
+            locale: "en",
+        }
+    }
+
+    fn render(source: &str, vars: &PromptVars) -> String {
+        Template::parse(source).unwrap().render(vars)
+    }
+
+    fn error(source: &str) -> String {
+        Template::parse(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn substitutes_variables() {
+        let source = "{{weekday}}, {{ date }}, {{time_of_day}} ({{locale}})";
+        assert_eq!(
+            render(source, &vars(None, 9)),
+            "Friday, January 31, 2025, morning (en)"
+        );
+        assert_eq!(render("{{time_of_day}}", &vars(None, 23)), "night");
+    }
+
+    #[test]
+    fn picks_if_or_else() {
+        let source = "Advice{{#if vibe}} that feels {{vibe}}{{else}} of any kind{{/if}}.";
+        assert_eq!(
+            render(source, &vars(Some("smug"), 9)),
+            "Advice that feels smug."
+        );
+        assert_eq!(render(source, &vars(None, 9)), "Advice of any kind.");
+        assert_eq!(render("{{#if vibe}}never shown{{/if}}", &vars(None, 9)), "");
+    }
+
+    #[test]
+    fn compares_with_a_value() {
+        let source = r#"{{#if weekday == "friday"}}Weekend soon.{{else}}Carry on.{{/if}}"#;
+        assert_eq!(render(source, &vars(None, 9)), "Weekend soon.");
+        let source = r#"{{#if time_of_day=="evening"}}Dim the lights.{{/if}}"#;
+        assert_eq!(render(source, &vars(None, 9)), "");
+        assert_eq!(render(source, &vars(None, 18)), "Dim the lights.");
+    }
+
+    #[test]
+    fn nests_blocks() {
+        let source = r#"{{#if vibe}}{{#if time_of_day == "morning"}}Early {{vibe}}{{else}}Late {{vibe}}{{/if}}{{else}}{{#if vibe}}unreachable{{else}}Plain{{/if}}{{/if}}!"#;
+        assert_eq!(render(source, &vars(Some("zen"), 9)), "Early zen!");
+        assert_eq!(render(source, &vars(Some("zen"), 14)), "Late zen!");
+        assert_eq!(render(source, &vars(None, 9)), "Plain!");
+    }
+
+    #[test]
+    fn reports_what_it_uses() {
+        let template =
+            Template::parse(r#"{{#if locale == "fr"}}{{#if vibe}}{{vibe}}{{/if}}{{/if}}"#).unwrap();
+        assert!(template.uses(Var::Locale));
+        assert!(template.uses(Var::Vibe));
+        assert!(!template.uses(Var::Date));
+    }
+
+    #[test]
+    fn rejects_unknown_variables_and_tags() {
+        assert_eq!(
+            error("Hi {{mood}}"),
+            "line 1, column 4: unknown variable \"mood\"; expected one of date, weekday, vibe, locale, time_of_day"
+        );
+        assert!(error("{{#if mood}}x{{/if}}")
+            .starts_with("line 1, column 1: unknown variable \"mood\""));
+        assert!(error("{{#each vibe}}").starts_with("line 1, column 1: unknown tag {{#each vibe}}"));
+        assert!(error("{{#if}}").contains("needs a variable to test"));
+        assert!(error("{{#if vibe == calm}}x{{/if}}").contains("compare with a quoted value"));
+    }
+
+    #[test]
+    fn reports_unbalanced_tags_where_they_are() {
+        assert_eq!(
+            error("Line one\n  {{vibe"),
+            "line 2, column 3: \"{{\" is never closed with \"}}\""
+        );
+        assert_eq!(
+            error("a\nb {{#if vibe}}\nc"),
+            "line 2, column 3: {{#if}} is never closed with {{/if}}"
+        );
+        assert_eq!(
+            error("{{#if vibe}}x{{/if}}\n{{/if}}"),
+            "line 2, column 1: {{/if}} without a matching {{#if}}"
+        );
+        assert_eq!(
+            error("é {{else}}"),
+            "line 1, column 3: {{else}} without a matching {{#if}}"
+        );
+        assert!(error("{{#if vibe}}a{{else}}b{{else}}c{{/if}}")
+            .starts_with("line 1, column 23: {{else}}"));
+    }
+}
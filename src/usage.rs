@@ -0,0 +1,117 @@
+//! Running totals of the tokens providers report using, per model, so the
+//! cost of the cookie is visible at `/api/stats`.
+
+use crate::provider::Usage;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageTotals {
+    /// Fortunes generated, whether or not the provider reported usage.
+    pub generations: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, usage: Option<Usage>) {
+        self.generations += 1;
+        if let Some(usage) = usage {
+            self.add_tokens(usage);
+        }
+    }
+
+    fn add_tokens(&mut self, usage: Usage) {
+        self.prompt_tokens += usage.prompt_tokens;
+        self.output_tokens += usage.output_tokens;
+        self.total_tokens += usage.total_tokens;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelUsage {
+    pub provider: &'static str,
+    pub model: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageSnapshot {
+    /// When counting started (server start), in RFC 3339.
+    pub since: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+    pub models: Vec<ModelUsage>,
+}
+
+pub struct UsageStats {
+    since: DateTime<Utc>,
+    by_model: Mutex<BTreeMap<(&'static str, String), UsageTotals>>,
+}
+
+impl UsageStats {
+    pub fn new() -> Self {
+        UsageStats {
+            since: Utc::now(),
+            by_model: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Counts one generation and logs what it cost.
+    pub fn record(&self, provider: &'static str, model: &str, usage: Option<Usage>) {
+        if let Some(usage) = usage {
+            println!(
+                "📊 {}/{} used {} prompt + {} output = {} tokens",
+                provider, model, usage.prompt_tokens, usage.output_tokens, usage.total_tokens
+            );
+        }
+        self.by_model
+            .lock()
+            .unwrap()
+            .entry((provider, model.to_string()))
+            .or_default()
+            .add(usage);
+    }
+
+    /// Counts the tokens of a request that produced no fortune.
+    pub fn record_unclaimed(&self, provider: &'static str, model: &str, usage: Usage) {
+        println!(
+            "📊 {}/{} used {} prompt + {} output = {} tokens for nothing",
+            provider, model, usage.prompt_tokens, usage.output_tokens, usage.total_tokens
+        );
+        self.by_model
+            .lock()
+            .unwrap()
+            .entry((provider, model.to_string()))
+            .or_default()
+            .add_tokens(usage);
+    }
+
+    pub fn snapshot(&self) -> UsageSnapshot {
+        let by_model = self.by_model.lock().unwrap();
+        let mut totals = UsageTotals::default();
+        let models = by_model
+            .iter()
+            .map(|((provider, model), usage)| {
+                totals.generations += usage.generations;
+                totals.prompt_tokens += usage.prompt_tokens;
+                totals.output_tokens += usage.output_tokens;
+                totals.total_tokens += usage.total_tokens;
+                ModelUsage {
+                    provider,
+                    model: model.clone(),
+                    totals: usage.clone(),
+                }
+            })
+            .collect();
+        UsageSnapshot {
+            since: self.since.to_rfc3339(),
+            totals,
+            models,
+        }
+    }
+}